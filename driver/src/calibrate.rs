@@ -0,0 +1,471 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{AppConfig, Calibration, MediaZone, TABLET_MAX_X};
+use crate::control::{ControlClient, DriverEvent, PenSample};
+
+/// How long the pen has to hover while the idle pressure is measured.
+const HOVER_TIME: Duration = Duration::from_secs(2);
+const MIN_HOVER_SAMPLES: usize = 20;
+const LIGHT_TOUCHES: usize = 3;
+/// Pressure drop above the hover noise that counts as contact before the
+/// lightest touch is known.
+const NOISE_MARGIN: i32 = 15;
+pub const CORNER_NAMES: [&str; 4] = ["top-left", "top-right", "bottom-right", "bottom-left"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    Hover,
+    LightTouch,
+    FullPress,
+    /// Index into `CORNER_NAMES`.
+    Corner(usize),
+    /// Leftmost media strip icon, which also marks where the strip starts.
+    MediaStrip,
+    /// Further media strip icons, counted from the left.
+    MediaZone(usize),
+    Done,
+}
+
+/// Steps through the calibration from the driver's raw pen samples. Each step
+/// finishes on its own once the pen has done what the instructions ask.
+pub struct Calibrator {
+    step: Step,
+    result: Calibration,
+    hover: Vec<i32>,
+    hover_started: Option<Instant>,
+    noise: i32,
+    /// Highest pressure drop of the current contact while the pen is down.
+    contact: Option<i32>,
+    light_touches: Vec<i32>,
+    corners: [[i32; 2]; 4],
+    /// Centers of the media strip icons tapped so far, in tablet units.
+    media_centers: Vec<i32>,
+    /// Why the current step is being repeated.
+    pub retry: Option<&'static str>,
+}
+
+impl Calibrator {
+    /// Values that aren't measured, if any, are kept from `current`.
+    pub fn new(current: &Calibration) -> Self {
+        Self {
+            step: Step::Hover,
+            result: current.clone(),
+            hover: Vec::new(),
+            hover_started: None,
+            noise: 0,
+            contact: None,
+            light_touches: Vec::new(),
+            corners: [[0; 2]; 4],
+            media_centers: Vec::new(),
+            retry: None,
+        }
+    }
+
+    pub fn step(&self) -> Step {
+        self.step
+    }
+
+    pub fn result(&self) -> Option<&Calibration> {
+        (self.step == Step::Done).then_some(&self.result)
+    }
+
+    fn zone_count(&self) -> usize {
+        self.result.media_zones.len().max(1)
+    }
+
+    /// Current step and the number of steps, both counted from 1.
+    pub fn progress(&self) -> (usize, usize) {
+        let total = 7 + self.zone_count();
+        let step = match self.step {
+            Step::Hover => 1,
+            Step::LightTouch => 2,
+            Step::FullPress => 3,
+            Step::Corner(i) => 4 + i,
+            Step::MediaStrip => 8,
+            Step::MediaZone(i) => 8 + i,
+            Step::Done => total,
+        };
+        (step, total)
+    }
+
+    pub fn instructions(&self) -> String {
+        match self.step {
+            Step::Hover => "Hold the pen still just above the tablet, without touching it.".to_string(),
+            Step::LightTouch => format!(
+                "Touch the tablet as lightly as you would still want to draw ({} of {}).",
+                self.light_touches.len() + 1,
+                LIGHT_TOUCHES
+            ),
+            Step::FullPress => "Press the pen as hard as you comfortably can, then lift it.".to_string(),
+            Step::Corner(i) => format!(
+                "Tap the {} corner of the drawing area.",
+                CORNER_NAMES[i]
+            ),
+            Step::MediaStrip => "Tap the middle of the leftmost icon on the media strip.".to_string(),
+            Step::MediaZone(i) => format!(
+                "Tap the middle of media strip icon {} of {}, counting from the left.",
+                i + 1,
+                self.zone_count()
+            ),
+            Step::Done => "Calibration finished.".to_string(),
+        }
+    }
+
+    pub fn record(&mut self, sample: &PenSample) {
+        self.record_at(sample, Instant::now());
+    }
+
+    fn record_at(&mut self, sample: &PenSample, now: Instant) {
+        if self.step == Step::Hover {
+            self.record_hover(sample.raw_pressure, now);
+            return;
+        }
+
+        let drop = self.result.pressure_idle - sample.raw_pressure;
+        let level = match self.step {
+            Step::LightTouch => self.noise + NOISE_MARGIN,
+            _ => self.result.pressure_threshold as i32,
+        };
+        if drop > level {
+            if self.contact.is_none() {
+                self.touch_down(sample);
+            }
+            self.contact = Some(self.contact.unwrap_or(0).max(drop));
+        } else if let Some(peak) = self.contact.take() {
+            self.touch_up(peak);
+        }
+    }
+
+    fn record_hover(&mut self, raw_pressure: i32, now: Instant) {
+        let started = *self.hover_started.get_or_insert(now);
+        self.hover.push(raw_pressure);
+        if now - started < HOVER_TIME || self.hover.len() < MIN_HOVER_SAMPLES {
+            return;
+        }
+
+        self.hover.sort_unstable();
+        let idle = self.hover[self.hover.len() / 2];
+        self.noise = self.hover.iter().map(|p| (idle - p).abs()).max().unwrap_or(0);
+        self.result.pressure_idle = idle;
+        self.step = Step::LightTouch;
+    }
+
+    fn touch_down(&mut self, sample: &PenSample) {
+        match self.step {
+            Step::Corner(i) => {
+                self.corners[i] = [sample.raw_x, sample.raw_y];
+                if i + 1 < CORNER_NAMES.len() {
+                    self.step = Step::Corner(i + 1);
+                } else {
+                    self.finish_corners();
+                }
+            }
+            Step::MediaStrip => {
+                if sample.raw_y < self.result.y_min {
+                    self.result.media_strip_y = (sample.raw_y + self.result.y_min) / 2;
+                    self.media_centers = vec![self.result.normalize_x(sample.raw_x)];
+                    self.retry = None;
+                    self.next_media_zone(1);
+                } else {
+                    self.retry = Some("That was inside the drawing area.");
+                }
+            }
+            Step::MediaZone(i) => {
+                let x = self.result.normalize_x(sample.raw_x);
+                if !self.result.is_media_strip(sample.raw_y) {
+                    self.retry = Some("That was inside the drawing area.");
+                } else if self.media_centers.last().is_some_and(|&last| x <= last) {
+                    self.retry = Some("Tap the icons from left to right.");
+                } else {
+                    self.media_centers.push(x);
+                    self.retry = None;
+                    self.next_media_zone(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn touch_up(&mut self, peak: i32) {
+        match self.step {
+            Step::LightTouch => {
+                self.light_touches.push(peak);
+                if self.light_touches.len() == LIGHT_TOUCHES {
+                    let lightest = self.light_touches.iter().copied().min().unwrap_or(0);
+                    // Halfway between the noise and the lightest touch, so both have some margin.
+                    let threshold = (self.noise + NOISE_MARGIN).max((self.noise + lightest) / 2);
+                    self.result.pressure_threshold = threshold as u16;
+                    self.step = Step::FullPress;
+                }
+            }
+            Step::FullPress => {
+                if peak > 2 * self.result.pressure_threshold as i32 {
+                    self.result.pressure_range = peak;
+                    self.step = Step::Corner(0);
+                    self.retry = None;
+                } else {
+                    self.retry = Some("That was barely more than a light touch.");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn next_media_zone(&mut self, i: usize) {
+        if i < self.zone_count() {
+            self.step = Step::MediaZone(i);
+            return;
+        }
+
+        // Zones meet halfway between icons; the outer ones are as wide as their neighbours' gap.
+        let centers = &self.media_centers;
+        self.result.media_zones = if centers.len() < 2 {
+            vec![MediaZone { x_min: 0, x_max: TABLET_MAX_X }]
+        } else {
+            let mut edges = vec![(centers[0] - (centers[1] - centers[0]) / 2).max(0)];
+            edges.extend(centers.windows(2).map(|pair| (pair[0] + pair[1]) / 2));
+            let n = centers.len();
+            edges.push((centers[n - 1] + (centers[n - 1] - centers[n - 2]) / 2).min(TABLET_MAX_X));
+            edges
+                .windows(2)
+                .map(|pair| MediaZone { x_min: pair[0], x_max: pair[1] })
+                .collect()
+        };
+        self.step = Step::Done;
+    }
+
+    fn finish_corners(&mut self) {
+        let [top_left, top_right, bottom_right, bottom_left] = self.corners;
+        let x_min = (top_left[0] + bottom_left[0]) / 2;
+        let x_max = (top_right[0] + bottom_right[0]) / 2;
+        let y_min = (top_left[1] + top_right[1]) / 2;
+        let y_max = (bottom_left[1] + bottom_right[1]) / 2;
+        if x_min >= x_max || y_min >= y_max {
+            self.retry = Some("The corners were tapped in the wrong order.");
+            self.step = Step::Corner(0);
+            return;
+        }
+        self.result.x_min = x_min;
+        self.result.x_max = x_max;
+        self.result.y_min = y_min;
+        self.result.y_max = y_max;
+        // Keep the old boundary valid until the media strip is tapped.
+        self.result.media_strip_y = self.result.media_strip_y.min(y_min);
+        self.retry = None;
+        self.step = Step::MediaStrip;
+    }
+}
+
+/// Puts new calibration values into `config`. Profiles go back to the
+/// calibrated threshold, as theirs were tuned to the old measurements.
+pub fn apply(config: &mut AppConfig, calibration: Calibration) {
+    config.calibration = calibration;
+    for profile in &mut config.profiles {
+        profile.pressure_threshold = None;
+    }
+}
+
+/// Runs the calibration in the terminal, with samples from the running driver.
+pub fn run_cli() -> Result<(), String> {
+    let driver = ControlClient::connect()
+        .map_err(|e| format!("Cannot reach the driver ({}). Start it first.", e))?;
+    // Saving defaults over a file that doesn't load would throw away the user's settings.
+    let mut config = AppConfig::try_load().map_err(|e| {
+        format!(
+            "Error loading {}: {}. Fix the file before calibrating.",
+            AppConfig::get_config_path().display(),
+            e
+        )
+    })?;
+
+    let mut calibrator = Calibrator::new(&config.calibration);
+    let mut shown = None;
+    while calibrator.step() != Step::Done {
+        let instructions = (calibrator.instructions(), calibrator.retry);
+        if shown.as_ref() != Some(&instructions) {
+            if let Some(retry) = instructions.1 {
+                println!("{}", retry);
+            }
+            println!("{}", instructions.0);
+            shown = Some(instructions);
+        }
+
+        let events = driver.poll().map_err(|_| "The driver has gone away.".to_string())?;
+        for event in events {
+            if let DriverEvent::PenSample(sample) = event {
+                calibrator.record(&sample);
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let result = calibrator.result().cloned().unwrap_or_default();
+    println!("{:#?}", result);
+    apply(&mut config, result);
+    config.save().map_err(|e| format!("Error saving settings: {}", e))?;
+    println!("Saved to {}", AppConfig::get_config_path().display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::even_media_zones;
+
+    const IDLE: i32 = 2000;
+
+    fn sample(raw_x: i32, raw_y: i32, raw_pressure: i32) -> PenSample {
+        PenSample {
+            raw_x,
+            raw_y,
+            raw_pressure,
+            x: 0,
+            y: 0,
+            pressure: 0,
+            media_strip: false,
+        }
+    }
+
+    /// Two seconds of hovering with ±3 of noise.
+    fn hover(calibrator: &mut Calibrator) {
+        let start = Instant::now();
+        for (i, noise) in [0, 3, -3].into_iter().cycle().take(MIN_HOVER_SAMPLES + 1).enumerate() {
+            let now = start + HOVER_TIME * i as u32 / MIN_HOVER_SAMPLES as u32;
+            calibrator.record_at(&sample(2000, 2000, IDLE + noise), now);
+        }
+    }
+
+    /// Puts the pen down at `(x, y)` with a pressure drop of `drop`, then lifts it.
+    fn touch(calibrator: &mut Calibrator, x: i32, y: i32, drop: i32) {
+        calibrator.record(&sample(x, y, IDLE - drop / 2));
+        calibrator.record(&sample(x, y, IDLE - drop));
+        calibrator.record(&sample(x, y, IDLE));
+    }
+
+    fn three_zones() -> Calibrator {
+        Calibrator::new(&Calibration {
+            media_zones: even_media_zones(3),
+            ..Default::default()
+        })
+    }
+
+    /// Hover, light touches, full press and the four corners.
+    fn through_corners(calibrator: &mut Calibrator) {
+        hover(calibrator);
+        for drop in [100, 80, 120] {
+            touch(calibrator, 2000, 2000, drop);
+        }
+        touch(calibrator, 2000, 2000, 1500);
+        for (x, y) in [(200, 300), (3900, 310), (3890, 3800), (210, 3790)] {
+            touch(calibrator, x, y, 500);
+        }
+    }
+
+    #[test]
+    fn full_calibration() {
+        let mut calibrator = three_zones();
+        assert_eq!(calibrator.step(), Step::Hover);
+        hover(&mut calibrator);
+        assert_eq!(calibrator.step(), Step::LightTouch);
+
+        for drop in [100, 80, 120] {
+            touch(&mut calibrator, 2000, 2000, drop);
+        }
+        assert_eq!(calibrator.step(), Step::FullPress);
+        touch(&mut calibrator, 2000, 2000, 1500);
+        assert_eq!(calibrator.step(), Step::Corner(0));
+
+        for (i, (x, y)) in [(200, 300), (3900, 310), (3890, 3800), (210, 3790)].into_iter().enumerate() {
+            assert_eq!(calibrator.step(), Step::Corner(i));
+            touch(&mut calibrator, x, y, 500);
+        }
+        assert_eq!(calibrator.step(), Step::MediaStrip);
+        touch(&mut calibrator, 400, 100, 500);
+        assert_eq!(calibrator.step(), Step::MediaZone(1));
+        touch(&mut calibrator, 1000, 100, 500);
+        assert_eq!(calibrator.step(), Step::MediaZone(2));
+        touch(&mut calibrator, 1600, 100, 500);
+        assert_eq!(calibrator.step(), Step::Done);
+        assert_eq!(calibrator.retry, None);
+
+        let result = calibrator.result().unwrap();
+        assert_eq!(result.pressure_idle, IDLE);
+        // Halfway between the noise of 3 and the lightest touch of 80.
+        assert_eq!(result.pressure_threshold, 41);
+        assert_eq!(result.pressure_range, 1500);
+        assert_eq!((result.x_min, result.x_max, result.y_min, result.y_max), (205, 3895, 305, 3795));
+        assert_eq!(result.media_strip_y, 202);
+        let zones: Vec<_> = result.media_zones.iter().map(|zone| (zone.x_min, zone.x_max)).collect();
+        assert_eq!(zones, [(0, 549), (549, 1215), (1215, 1881)]);
+
+        let mut config = AppConfig::default();
+        config.profiles[0].pressure_threshold = Some(300);
+        apply(&mut config, result.clone());
+        assert_eq!(config.profiles[0].pressure_threshold, None);
+        assert!(config.validate().is_valid());
+    }
+
+    #[test]
+    fn hover_waits_for_time_and_samples() {
+        let mut calibrator = three_zones();
+        let start = Instant::now();
+        for i in 0..MIN_HOVER_SAMPLES * 2 {
+            calibrator.record_at(&sample(0, 0, IDLE), start + Duration::from_millis(i as u64));
+        }
+        assert_eq!(calibrator.step(), Step::Hover);
+        calibrator.record_at(&sample(0, 0, IDLE), start + HOVER_TIME);
+        assert_eq!(calibrator.step(), Step::LightTouch);
+    }
+
+    #[test]
+    fn weak_full_press_is_retried() {
+        let mut calibrator = three_zones();
+        hover(&mut calibrator);
+        for drop in [100, 80, 120] {
+            touch(&mut calibrator, 2000, 2000, drop);
+        }
+        touch(&mut calibrator, 2000, 2000, 60);
+        assert_eq!(calibrator.step(), Step::FullPress);
+        assert_eq!(calibrator.retry, Some("That was barely more than a light touch."));
+        touch(&mut calibrator, 2000, 2000, 1500);
+        assert_eq!(calibrator.step(), Step::Corner(0));
+        assert_eq!(calibrator.retry, None);
+    }
+
+    #[test]
+    fn corners_in_the_wrong_order_start_over() {
+        let mut calibrator = three_zones();
+        hover(&mut calibrator);
+        for drop in [100, 80, 120, 1500] {
+            touch(&mut calibrator, 2000, 2000, drop);
+        }
+        for (x, y) in [(3890, 3800), (210, 3790), (200, 300), (3900, 310)] {
+            touch(&mut calibrator, x, y, 500);
+        }
+        assert_eq!(calibrator.step(), Step::Corner(0));
+        assert_eq!(calibrator.retry, Some("The corners were tapped in the wrong order."));
+    }
+
+    #[test]
+    fn media_taps_off_the_strip_or_out_of_order_are_retried() {
+        let mut calibrator = three_zones();
+        through_corners(&mut calibrator);
+
+        touch(&mut calibrator, 400, 1000, 500);
+        assert_eq!(calibrator.step(), Step::MediaStrip);
+        assert_eq!(calibrator.retry, Some("That was inside the drawing area."));
+        touch(&mut calibrator, 400, 100, 500);
+        assert_eq!(calibrator.step(), Step::MediaZone(1));
+
+        touch(&mut calibrator, 300, 100, 500);
+        assert_eq!(calibrator.step(), Step::MediaZone(1));
+        assert_eq!(calibrator.retry, Some("Tap the icons from left to right."));
+        touch(&mut calibrator, 1000, 1000, 500);
+        assert_eq!(calibrator.retry, Some("That was inside the drawing area."));
+        touch(&mut calibrator, 1000, 100, 500);
+        assert_eq!(calibrator.step(), Step::MediaZone(2));
+        assert_eq!(calibrator.retry, None);
+        assert!(calibrator.result().is_none());
+    }
+}
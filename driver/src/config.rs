@@ -1,55 +1,633 @@
+mod action;
+mod calibration;
+mod format;
+mod migration;
+mod pressure;
+mod validation;
+
+pub use action::{
+    Action, ButtonTiming, DragMode, DragSettings, KeyRepeat, Mode, MouseButton, RepeatMode,
+    RepeatedKeys, ScrollDirection, SequenceStep,
+};
+pub use calibration::{even_media_zones, Calibration, MediaZone, PressureDrift, DEFAULT_SENSITIVITY};
+pub use format::ConfigFormat;
+pub use pressure::{evaluate_curve, PressureCurve, TipSettings, CURVE_PRESETS};
+
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use directories::ProjectDirs;
 
 const APP_QUALIFIER: &str = "com";
 const APP_ORG: &str = "theninth";
 const APP_NAME: &str = "v1060p-driver";
+const CONFIG_FILE_STEM: &str = "settings";
+
+/// Bump together with a new step in `migration::MIGRATIONS` whenever a field is
+/// renamed or changes meaning. Added fields only need a default.
+pub const CONFIG_VERSION: u32 = 5;
+
+pub const TABLET_MAX_X: i32 = 4095;
+pub const TABLET_MAX_Y: i32 = 4095;
+/// Physical size of the active area; raw units are not square.
+pub const TABLET_WIDTH_MM: f32 = 254.0;
+pub const TABLET_HEIGHT_MM: f32 = 158.75;
+/// Upper end of the pressure axis of the virtual pen.
+pub const MAX_PRESSURE: i32 = 8191;
+pub const SENSITIVITY_RANGE: RangeInclusive<f32> = 0.1..=10.0;
+
+/// Bits 10 and 11 of the tablet button report are not wired to any button.
+pub const TABLET_BUTTON_IDS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 12, 13];
+pub const PEN_BUTTON_IDS: [u8; 2] = [4, 6];
+/// Icons printed on the media strip; the calibration can set up a different number of zones.
+pub const MEDIA_BUTTONS_COUNT: u8 = 10;
+
+/// Button id to the action it triggers.
+pub type ButtonMap = BTreeMap<u8, Action>;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse { line: usize, column: usize, message: String },
+    UnsupportedVersion(u32),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{}", e),
+            ConfigError::Parse { line, column, message } if *line > 0 => {
+                write!(f, "line {}, column {}: {}", line, column, message)
+            }
+            ConfigError::Parse { message, .. } => write!(f, "{}", message),
+            ConfigError::UnsupportedVersion(version) => write!(
+                f,
+                "config version {} is newer than this driver supports ({})",
+                version, CONFIG_VERSION
+            ),
+            ConfigError::Invalid(errors) => write!(f, "invalid settings: {}", errors.join("; ")),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> Self {
+        // serde_json appends the location to its message; it is reported separately.
+        let location = format!(" at line {} column {}", e.line(), e.column());
+        let message = e.to_string();
+        ConfigError::Parse {
+            line: e.line(),
+            column: e.column(),
+            message: message.strip_suffix(&location).unwrap_or(&message).to_string(),
+        }
+    }
+}
+
+// Missing fields in all config structs are filled from their `Default` impl, so
+// older files keep their settings when new fields are added.
+
+/// Part of the tablet surface, in raw tablet units, that is stretched over the whole output.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct TabletArea {
+    pub x_min: i32,
+    pub y_min: i32,
+    pub x_max: i32,
+    pub y_max: i32,
+}
+
+impl Default for TabletArea {
+    fn default() -> Self {
+        Self {
+            x_min: 0,
+            y_min: 0,
+            x_max: TABLET_MAX_X,
+            y_max: TABLET_MAX_Y,
+        }
+    }
+}
+
+/// Part of the desktop the tablet area is mapped onto, as fractions of the
+/// bounding box of all monitors.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct OutputArea {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for OutputArea {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+        }
+    }
+}
+
+/// How the tip and hovering move the cursor and click. The media strip works
+/// the same in every mode.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum PenMode {
+    /// Hovering moves the cursor, the tip clicks and draws.
+    #[default]
+    Normal,
+    /// Hovering and touching only move the cursor; clicks come from buttons.
+    TipClickDisabled,
+    /// The cursor only moves while the tip touches the tablet.
+    TouchOnly,
+}
+
+/// What touching the media strip does.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum MediaStripMode {
+    /// Each zone is a button with its own mapping in `media_buttons`.
+    #[default]
+    Buttons,
+    /// Dragging along the strip repeats `media_slider.action`.
+    Slider,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum SliderAction {
+    #[default]
+    Volume,
+    Scroll,
+    /// Ctrl + mouse wheel.
+    Zoom,
+    /// The `[` and `]` keys of most painting programs.
+    BrushSize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct MediaSlider {
+    pub action: SliderAction,
+    /// Distance along the strip, in tablet units, for one step. Moving right steps up.
+    pub step: i32,
+}
+
+impl Default for MediaSlider {
+    fn default() -> Self {
+        Self {
+            action: SliderAction::default(),
+            step: 200,
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct AppConfig {
-    pub pressure_threshold: u16,
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    /// Overrides `Calibration::pressure_threshold` for this profile.
+    pub pressure_threshold: Option<u16>,
     pub sensitivity: f32,
+    pub pressure_curve: PressureCurve,
+    /// 0.0 disables coordinate smoothing, 1.0 is the strongest smoothing.
+    pub smoothing: f32,
+    /// Movements of a still pen within this radius, in tablet units, are
+    /// ignored until it clearly moves. 0 disables the dead zone.
+    pub dead_zone: i32,
+    pub area: TabletArea,
+    pub output: OutputArea,
+    /// The tablet is turned by 180° with the buttons on the right. `area` is
+    /// given as seen in that orientation.
+    pub left_handed: bool,
+    pub pen_mode: PenMode,
+    #[serde(with = "button_ids")]
+    pub tablet_buttons: ButtonMap,
+    /// Tablet button actions for a long press. Buttons without one act on tap.
+    #[serde(with = "button_ids")]
+    pub tablet_hold: ButtonMap,
+    /// Tablet button actions for two quick taps.
+    #[serde(with = "button_ids")]
+    pub tablet_double_tap: ButtonMap,
+    #[serde(with = "button_ids")]
+    pub pen_buttons: ButtonMap,
+    #[serde(with = "button_ids")]
+    pub media_buttons: ButtonMap,
+    /// Tablet button actions while `layer_shift_button` is held. Buttons
+    /// missing here keep their normal action.
+    #[serde(with = "button_ids")]
+    pub shifted_tablet_buttons: ButtonMap,
+    /// Media strip zone actions while `layer_shift_button` is held.
+    #[serde(with = "button_ids")]
+    pub shifted_media_buttons: ButtonMap,
+    pub media_mode: MediaStripMode,
+    pub media_slider: MediaSlider,
+    pub drag: DragSettings,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new("Default")
+    }
+}
+
+impl Profile {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            pressure_threshold: None,
+            sensitivity: DEFAULT_SENSITIVITY,
+            pressure_curve: pressure::linear_curve(),
+            smoothing: 1.0,
+            dead_zone: 0,
+            area: TabletArea::default(),
+            output: OutputArea::default(),
+            left_handed: false,
+            pen_mode: PenMode::default(),
+            tablet_buttons: button_map(&[
+                (0, &["KEY_TAB"]),                       // TAB
+                (1, &["KEY_SPACE"]),                     // SPACE
+                (2, &["KEY_LEFTALT"]),                   // ALT
+                (3, &["KEY_LEFTCTRL"]),                  // CTRL
+                (4, &["KEY_PAGEUP"]),                    // MOUSE UP
+                (5, &["KEY_PAGEDOWN"]),                  // MOUSE DOWN
+                (6, &["KEY_LEFTBRACE"]),                 // MOUSE AREA -
+                (7, &["KEY_LEFTCTRL", "KEY_KPMINUS"]),   // CTRL- ZOOM
+                (8, &["KEY_LEFTCTRL", "KEY_KPPLUS"]),    // CTRL+ ZOOM
+                (9, &["KEY_ESC"]),                       // ESC CANCEL
+                (12, &["KEY_B"]),                        // TOGGLE MOUSE/TABLET
+                (13, &["KEY_RIGHTBRACE"]),               // MOUSE AREA +
+            ]),
+            tablet_hold: ButtonMap::new(),
+            tablet_double_tap: ButtonMap::new(),
+            pen_buttons: button_map(&[(4, &["BTN_STYLUS"]), (6, &["BTN_STYLUS2"])]),
+            media_buttons: button_map(&[
+                (0, &["KEY_MUTE"]),
+                (1, &["KEY_VOLUMEDOWN"]),
+                (2, &["KEY_VOLUMEUP"]),
+                (3, &["KEY_PLAYER"]),
+                (4, &["KEY_PLAYPAUSE"]),
+                (5, &["KEY_PREVIOUSSONG"]),
+                (6, &["KEY_NEXTSONG"]),
+                (7, &["KEY_HOME"]),
+                (8, &["KEY_CALC"]),
+                (9, &["KEY_LEFTMETA", "KEY_D"]),
+            ]),
+            shifted_tablet_buttons: ButtonMap::new(),
+            shifted_media_buttons: ButtonMap::new(),
+            media_mode: MediaStripMode::default(),
+            media_slider: MediaSlider::default(),
+            drag: DragSettings::default(),
+        }
+    }
+}
+
+/// TOML tables only have string keys, so button ids are written as strings.
+mod button_ids {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S: Serializer, V: Serialize>(
+        map: &BTreeMap<u8, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(map.iter().map(|(id, value)| (id.to_string(), value)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<u8, V>, D::Error> {
+        BTreeMap::<String, V>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(id, value)| match id.parse() {
+                Ok(id) => Ok((id, value)),
+                Err(_) => Err(D::Error::custom(format!("invalid button id \"{}\"", id))),
+            })
+            .collect()
+    }
+}
+
+fn button_map(entries: &[(u8, &[&str])]) -> ButtonMap {
+    entries
+        .iter()
+        .map(|(id, keys)| (*id, Action::keys(keys)))
+        .collect()
+}
+
+/// Activates `profile` while a window with this class or process name has focus.
+/// Names are compared case-insensitively.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ProfileRule {
+    pub profile: String,
+    pub window_class: Option<String>,
+    pub process_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum FocusDetector {
+    #[default]
+    Auto,
+    Disabled,
+    X11,
+    SwayIpc,
+    Gnome,
+    Kde,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AppConfig {
+    pub version: u32,
+    pub active_profile: String,
+    /// Tablet button that switches to the next profile instead of emitting its keys.
+    pub profile_cycle_button: Option<u8>,
+    /// Tablet button that switches the other buttons and the media strip to
+    /// their shifted actions while it is held.
+    pub layer_shift_button: Option<u8>,
+    pub profiles: Vec<Profile>,
+    pub profile_rules: Vec<ProfileRule>,
+    pub focus_detector: FocusDetector,
+    pub calibration: Calibration,
+    pub pressure_drift: PressureDrift,
+    pub tip: TipSettings,
+    pub button_timing: ButtonTiming,
+    pub key_repeat: KeyRepeat,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
+        let profile = Profile::default();
         Self {
-            pressure_threshold: 510,
-            sensitivity: 5.0,
+            version: CONFIG_VERSION,
+            active_profile: profile.name.clone(),
+            profile_cycle_button: None,
+            layer_shift_button: None,
+            profiles: vec![profile],
+            profile_rules: Vec::new(),
+            focus_detector: FocusDetector::default(),
+            calibration: Calibration::default(),
+            pressure_drift: PressureDrift::default(),
+            tip: TipSettings::default(),
+            button_timing: ButtonTiming::default(),
+            key_repeat: KeyRepeat::default(),
         }
     }
 }
 
 impl AppConfig {
-    pub fn load() -> Self {
-        let config_path = Self::get_config_path();
-        if config_path.exists() {
-            let content = fs::read_to_string(config_path).unwrap_or_default();
-            serde_json::from_str(&content).unwrap_or_else(|_| Self::default())
+    /// Loads the config, migrating it to `CONFIG_VERSION` if needed. A migrated
+    /// file is rewritten after the original is copied to `settings.*.v<N>.bak`.
+    /// Configs that fail validation are rejected; warnings are left to the caller.
+    pub fn try_load() -> Result<Self, ConfigError> {
+        Self::load_from(&Self::get_config_path())
+    }
+
+    fn load_from(config_path: &Path) -> Result<Self, ConfigError> {
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(config_path)?;
+        let (config, old_version) = Self::parse(&content, ConfigFormat::from_path(config_path))?;
+
+        let validation = config.validate();
+        if !validation.is_valid() {
+            return Err(ConfigError::Invalid(validation.errors));
+        }
+
+        if let Some(old_version) = old_version {
+            let backup = with_suffix(config_path, &format!(".v{}.bak", old_version));
+            fs::copy(config_path, &backup)?;
+            config.save_to(config_path)?;
+            println!("Migrated config from version {} to {} (backup: {})",
+                old_version, CONFIG_VERSION, backup.display());
+        }
+        Ok(config)
+    }
+
+    /// Parses a config document. Returns the version it was migrated from, if any.
+    pub fn parse(content: &str, format: ConfigFormat) -> Result<(Self, Option<u32>), ConfigError> {
+        let document = format.document(content)?;
+        let version = migration::detect_version(&document);
+
+        let (mut config, old_version) = if version > CONFIG_VERSION {
+            return Err(ConfigError::UnsupportedVersion(version));
+        } else if version == CONFIG_VERSION {
+            // Parse the text again so type errors still carry a line and column.
+            (format.deserialize(content)?, None)
         } else {
-            Self::default()
+            let document = migration::migrate(document, version);
+            (serde_json::from_value::<Self>(document)?, Some(version))
+        };
+
+        config.version = CONFIG_VERSION;
+        if config.profiles.is_empty() {
+            config.profiles.push(Profile::default());
         }
+        Ok((config, old_version))
     }
 
+    /// Writes the config next to the old one and renames it into place, so
+    /// readers never see a partial file. The previous file is kept as `*.bak`.
     pub fn save(&self) -> Result<(), std::io::Error> {
-        let config_path = Self::get_config_path();
-        
+        self.save_to(&Self::get_config_path())
+    }
+
+    fn save_to(&self, config_path: &Path) -> Result<(), std::io::Error> {
         if let Some(parent) = config_path.parent() {
             fs::create_dir_all(parent)?;
         }
 
-        let content = serde_json::to_string_pretty(self)?;
-        fs::write(config_path, content)?;
+        let existing = fs::read_to_string(config_path).ok();
+        let content = ConfigFormat::from_path(config_path)
+            .serialize(self, existing.as_deref())
+            .map_err(io::Error::other)?;
+
+        let temp_path = with_suffix(config_path, ".tmp");
+        let mut file = File::create(&temp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+
+        if existing.is_some() {
+            fs::copy(config_path, with_suffix(config_path, ".bak"))?;
+        }
+        fs::rename(&temp_path, config_path)?;
         Ok(())
     }
 
-    pub fn get_config_path() -> PathBuf {
+    fn get_config_dir() -> PathBuf {
         if let Some(proj_dirs) = ProjectDirs::from(APP_QUALIFIER, APP_ORG, APP_NAME) {
-            proj_dirs.config_dir().join("settings.json")
+            proj_dirs.config_dir().to_path_buf()
         } else {
-            PathBuf::from("settings.json")
+            PathBuf::new()
         }
     }
+
+    /// `settings.toml` if the user created one, otherwise `settings.json`.
+    pub fn get_config_path() -> PathBuf {
+        let dir = Self::get_config_dir();
+        ConfigFormat::ALL
+            .iter()
+            .map(|format| dir.join(format!("{}.{}", CONFIG_FILE_STEM, format.extension())))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| dir.join(format!("{}.json", CONFIG_FILE_STEM)))
+    }
+
+    pub fn is_config_file_name(name: &OsStr) -> bool {
+        ConfigFormat::ALL
+            .iter()
+            .any(|format| name == format!("{}.{}", CONFIG_FILE_STEM, format.extension()).as_str())
+    }
+
+    /// The active profile, falling back to the first one if `active_profile` names nothing.
+    pub fn active(&self) -> &Profile {
+        self.profiles
+            .iter()
+            .find(|p| p.name == self.active_profile)
+            .or_else(|| self.profiles.first())
+            .expect("config has no profiles")
+    }
+
+    pub fn active_mut(&mut self) -> &mut Profile {
+        let index = self.active_index();
+        &mut self.profiles[index]
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.profiles
+            .iter()
+            .position(|p| p.name == self.active_profile)
+            .unwrap_or(0)
+    }
+
+    /// Makes the profile after the active one active, wrapping around. Returns its name.
+    pub fn cycle_profile(&mut self) -> &str {
+        let next = (self.active_index() + 1) % self.profiles.len();
+        self.active_profile = self.profiles[next].name.clone();
+        &self.active_profile
+    }
+
+    /// Renames a profile along with the rules and `SwitchProfile` actions that use it.
+    pub fn rename_profile(&mut self, from: &str, to: &str) {
+        if self.active_profile == from {
+            self.active_profile = to.to_string();
+        }
+        for rule in &mut self.profile_rules {
+            if rule.profile == from {
+                rule.profile = to.to_string();
+            }
+        }
+        for p in &mut self.profiles {
+            if p.name == from {
+                p.name = to.to_string();
+            }
+            let maps = [
+                &mut p.tablet_buttons,
+                &mut p.tablet_hold,
+                &mut p.tablet_double_tap,
+                &mut p.pen_buttons,
+                &mut p.media_buttons,
+                &mut p.shifted_tablet_buttons,
+                &mut p.shifted_media_buttons,
+            ];
+            for action in maps.into_iter().flat_map(|map| map.values_mut()) {
+                if let Action::SwitchProfile(target) = action
+                    && target == from
+                {
+                    *target = to.to_string();
+                }
+            }
+        }
+    }
+
+    /// Shell commands of the `Command` actions in every profile.
+    pub fn commands(&self) -> BTreeSet<&str> {
+        self.profiles
+            .iter()
+            .flat_map(|p| {
+                [
+                    &p.tablet_buttons,
+                    &p.tablet_hold,
+                    &p.tablet_double_tap,
+                    &p.pen_buttons,
+                    &p.media_buttons,
+                    &p.shifted_tablet_buttons,
+                    &p.shifted_media_buttons,
+                ]
+            })
+            .flat_map(|map| map.values())
+            .filter_map(|action| match action {
+                Action::Command(command) => Some(command.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renaming_a_profile_updates_rules_and_switch_actions() {
+        let mut config = AppConfig::default();
+        config.profiles.push(Profile::new("Krita"));
+        config.active_profile = "Krita".to_string();
+        config.profile_rules.push(ProfileRule {
+            profile: "Krita".to_string(),
+            window_class: Some("krita".to_string()),
+            ..Default::default()
+        });
+        config.profiles[0].tablet_buttons.insert(1, Action::SwitchProfile("Krita".to_string()));
+        config.profiles[1].pen_buttons.insert(1, Action::SwitchProfile("Default".to_string()));
+        config.profiles[1].tablet_hold.insert(2, Action::SwitchProfile("Krita".to_string()));
+
+        config.rename_profile("Krita", "Painting");
+
+        assert_eq!(config.profiles[1].name, "Painting");
+        assert_eq!(config.active_profile, "Painting");
+        assert_eq!(config.profile_rules[0].profile, "Painting");
+        assert_eq!(config.profiles[0].tablet_buttons[&1], Action::SwitchProfile("Painting".to_string()));
+        assert_eq!(config.profiles[1].tablet_hold[&2], Action::SwitchProfile("Painting".to_string()));
+        assert_eq!(config.profiles[1].pen_buttons[&1], Action::SwitchProfile("Default".to_string()));
+        let warnings = config.validate().warnings;
+        assert!(!warnings.iter().any(|warning| warning.contains("unknown profile")), "{:?}", warnings);
+    }
+
+    #[test]
+    fn migrated_file_is_backed_up_and_rewritten() {
+        let dir = std::env::temp_dir().join(format!("v1060p-migration-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.json");
+        let v1 = r#"{ "pressure_threshold": 510, "sensitivity": 1.5 }"#;
+        fs::write(&path, v1).unwrap();
+
+        let config = AppConfig::load_from(&path).unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("settings.json.v1.bak")).unwrap(), v1);
+        let (rewritten, old_version) =
+            AppConfig::parse(&fs::read_to_string(&path).unwrap(), ConfigFormat::Json).unwrap();
+        assert_eq!(old_version, None);
+        assert_eq!(rewritten.version, CONFIG_VERSION);
+        assert_eq!(rewritten.profiles[0].sensitivity, config.profiles[0].sensitivity);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
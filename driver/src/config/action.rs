@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+
+/// What a tablet, pen or media strip button does.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum Action {
+    #[default]
+    None,
+    /// evdev key names held together while the button is down, modifiers first.
+    Keys(Vec<String>),
+    /// Like `Keys`, but repeated by the driver with its own delay and interval,
+    /// whatever `KeyRepeat::mode` is.
+    RepeatKeys(RepeatedKeys),
+    /// Key chords tapped one after another.
+    Sequence(Vec<SequenceStep>),
+    Mouse(MouseButton),
+    /// One wheel notch per press.
+    Scroll(ScrollDirection),
+    /// Typed as if on a US layout; other characters go through Ctrl+Shift+U.
+    Text(String),
+    /// Run with `sh -c`, detached from the driver.
+    Command(String),
+    SwitchProfile(String),
+    ToggleMode(Mode),
+    /// While the button is held, moving the pen scrolls, pans or zooms.
+    Drag(DragMode),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct SequenceStep {
+    pub keys: Vec<String>,
+    /// Pause after the chord is released.
+    pub delay_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RepeatedKeys {
+    pub keys: Vec<String>,
+    pub delay_ms: u64,
+    pub interval_ms: u64,
+}
+
+impl Default for RepeatedKeys {
+    fn default() -> Self {
+        let repeat = KeyRepeat::default();
+        Self {
+            keys: Vec::new(),
+            delay_ms: repeat.delay_ms,
+            interval_ms: repeat.interval_ms,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum MouseButton {
+    #[default]
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ScrollDirection {
+    #[default]
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Settings of the active profile a button can flip until the config is reloaded.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Mode {
+    /// Media strip between buttons and slider.
+    #[default]
+    MediaSlider,
+    LeftHanded,
+    /// Pen mode between normal and tip click disabled.
+    TipClick,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum DragMode {
+    /// Smooth wheel scrolling; the cursor stays put.
+    #[default]
+    Scroll,
+    /// Middle button drag, which pans the canvas in most applications.
+    Pan,
+    /// Ctrl+wheel from vertical movement; up zooms in.
+    Zoom,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct DragSettings {
+    /// Pen movement, in tablet units, for one wheel notch.
+    pub step: i32,
+    /// Scroll the other way: the content moves against the pen.
+    pub invert: bool,
+}
+
+impl Default for DragSettings {
+    fn default() -> Self {
+        Self {
+            step: 100,
+            invert: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum RepeatMode {
+    /// Keys stay down while the button is held and the desktop repeats them
+    /// with its keyboard settings.
+    #[default]
+    System,
+    /// The driver repeats keys with `KeyRepeat`'s delay and interval.
+    Driver,
+}
+
+/// Autorepeat of key actions while their button is held.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct KeyRepeat {
+    pub mode: RepeatMode,
+    pub delay_ms: u64,
+    pub interval_ms: u64,
+    /// Also repeat Ctrl, Shift, Alt and Super when the driver repeats keys.
+    pub repeat_modifiers: bool,
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        Self {
+            mode: RepeatMode::default(),
+            delay_ms: 500,
+            interval_ms: 40,
+            repeat_modifiers: false,
+        }
+    }
+}
+
+/// How tablet buttons with a hold or double-tap binding tell the gestures apart.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct ButtonTiming {
+    /// A press at least this long is a hold.
+    pub hold_ms: u64,
+    /// Longest wait for the second tap of a double-tap.
+    pub double_tap_ms: u64,
+}
+
+impl Default for ButtonTiming {
+    fn default() -> Self {
+        Self {
+            hold_ms: 500,
+            double_tap_ms: 250,
+        }
+    }
+}
+
+impl Action {
+    pub fn keys(names: &[&str]) -> Self {
+        Action::Keys(names.iter().map(|name| name.to_string()).collect())
+    }
+
+    /// Names of every key the action presses, for validation.
+    pub fn key_names(&self) -> Vec<&String> {
+        match self {
+            Action::Keys(keys) => keys.iter().collect(),
+            Action::RepeatKeys(repeated) => repeated.keys.iter().collect(),
+            Action::Sequence(steps) => steps.iter().flat_map(|step| &step.keys).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+use super::{MEDIA_BUTTONS_COUNT, TABLET_MAX_X, TABLET_MAX_Y};
+
+/// Sensitivity at which a press of `pressure_range` reaches full pressure.
+pub const DEFAULT_SENSITIVITY: f32 = 5.0;
+
+/// Part of the media strip that acts as one button, in tablet units along X.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct MediaZone {
+    pub x_min: i32,
+    pub x_max: i32,
+}
+
+/// Zones of equal width across the whole strip.
+pub fn even_media_zones(count: u8) -> Vec<MediaZone> {
+    let count = count.max(1) as i32;
+    (0..count)
+        .map(|i| MediaZone {
+            x_min: i * TABLET_MAX_X / count,
+            x_max: (i + 1) * TABLET_MAX_X / count,
+        })
+        .collect()
+}
+
+/// Measurements of this particular tablet and pen, taken by the calibration
+/// wizard. Raw coordinates are rescaled from the measured extents to
+/// `0..=TABLET_MAX_X` x `0..=TABLET_MAX_Y` before anything else uses them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Calibration {
+    /// Raw pressure of a hovering pen; pressing lowers the value.
+    pub pressure_idle: i32,
+    /// Drop below `pressure_idle` that counts as touching, unless a profile overrides it.
+    pub pressure_threshold: u16,
+    /// Drop below `pressure_idle` of the hardest press.
+    pub pressure_range: i32,
+    pub x_min: i32,
+    pub x_max: i32,
+    pub y_min: i32,
+    pub y_max: i32,
+    /// Raw Y below which the pen is on the media strip.
+    pub media_strip_y: i32,
+    /// Zone `i` triggers `media_buttons[i]`, left to right.
+    pub media_zones: Vec<MediaZone>,
+    /// Tablet units at each zone edge where touches are ignored, so a tap
+    /// between two icons triggers neither.
+    pub media_dead_band: i32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            pressure_idle: 2000,
+            pressure_threshold: 510,
+            pressure_range: 1638,
+            x_min: 0,
+            x_max: TABLET_MAX_X,
+            y_min: 0,
+            y_max: TABLET_MAX_Y,
+            media_strip_y: 0,
+            media_zones: even_media_zones(MEDIA_BUTTONS_COUNT),
+            media_dead_band: 0,
+        }
+    }
+}
+
+impl Calibration {
+    pub fn normalize_x(&self, raw_x: i32) -> i32 {
+        rescale(raw_x, self.x_min, self.x_max, TABLET_MAX_X)
+    }
+
+    pub fn normalize_y(&self, raw_y: i32) -> i32 {
+        rescale(raw_y, self.y_min, self.y_max, TABLET_MAX_Y)
+    }
+
+    pub fn is_media_strip(&self, raw_y: i32) -> bool {
+        raw_y < self.media_strip_y
+    }
+
+    /// Index of the media zone at `x` in tablet units, outside of any dead band.
+    pub fn media_zone_at(&self, x: i32) -> Option<u8> {
+        let margin = self.media_dead_band / 2;
+        self.media_zones
+            .iter()
+            .position(|zone| x >= zone.x_min + margin && x <= zone.x_max - margin)
+            .map(|i| i as u8)
+    }
+}
+
+fn rescale(value: i32, min: i32, max: i32, target_max: i32) -> i32 {
+    ((value - min) * target_max / (max - min).max(1)).clamp(0, target_max)
+}
+
+/// Follows slow changes of the idle pressure while the pen hovers, so a
+/// drifting pen neither touches on its own nor needs more force to draw.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PressureDrift {
+    pub enabled: bool,
+    /// Largest correction of `Calibration::pressure_idle`, in raw pressure units.
+    pub max_drift: i32,
+    /// Share of the deviation the baseline follows per second, 0..=1.
+    pub rate: f32,
+    /// Only hover readings this close to the baseline move it, in raw pressure
+    /// units, so a slow press isn't taken for drift.
+    pub noise_band: i32,
+}
+
+impl Default for PressureDrift {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_drift: 300,
+            rate: 0.05,
+            noise_band: 40,
+        }
+    }
+}
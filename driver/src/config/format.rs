@@ -0,0 +1,184 @@
+use std::path::Path;
+
+use toml_edit::{DocumentMut, Item, Table};
+
+use super::{AppConfig, ConfigError};
+
+/// On-disk formats of `settings.*`. TOML is offered for hand-edited configs:
+/// saving merges into the existing document, so comments are kept.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    pub const ALL: [ConfigFormat; 2] = [ConfigFormat::Toml, ConfigFormat::Json];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "json",
+            ConfigFormat::Toml => "toml",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    /// Generic view of the document, used to detect and migrate old versions.
+    pub fn document(self, content: &str) -> Result<serde_json::Value, ConfigError> {
+        match self {
+            ConfigFormat::Json => Ok(serde_json::from_str(content)?),
+            ConfigFormat::Toml => {
+                let value: toml::Value =
+                    toml::from_str(content).map_err(|e| toml_error(content, e))?;
+                Ok(serde_json::to_value(value)?)
+            }
+        }
+    }
+
+    pub fn deserialize(self, content: &str) -> Result<AppConfig, ConfigError> {
+        match self {
+            ConfigFormat::Json => Ok(serde_json::from_str(content)?),
+            ConfigFormat::Toml => toml::from_str(content).map_err(|e| toml_error(content, e)),
+        }
+    }
+
+    /// Serializes `config`. For TOML, `existing` is the current file, whose
+    /// comments and layout are kept for every key that still exists.
+    pub fn serialize(self, config: &AppConfig, existing: Option<&str>) -> Result<String, String> {
+        match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(config).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => {
+                let fresh: DocumentMut = toml::to_string_pretty(config)
+                    .map_err(|e| e.to_string())?
+                    .parse()
+                    .map_err(|e: toml_edit::TomlError| e.to_string())?;
+                match existing.and_then(|content| content.parse::<DocumentMut>().ok()) {
+                    Some(mut document) => {
+                        merge_table(document.as_table_mut(), fresh.as_table());
+                        Ok(document.to_string())
+                    }
+                    None => Ok(fresh.to_string()),
+                }
+            }
+        }
+    }
+}
+
+fn toml_error(content: &str, e: toml::de::Error) -> ConfigError {
+    let (line, column) = match e.span() {
+        Some(span) => {
+            let before = &content[..span.start];
+            let line = before.matches('\n').count() + 1;
+            let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+            (line, column)
+        }
+        None => (0, 0),
+    };
+    ConfigError::Parse {
+        line,
+        column,
+        message: e.message().to_string(),
+    }
+}
+
+/// Makes `target` hold the values of `source` while keeping the comments and
+/// formatting attached to keys and tables that exist in both.
+fn merge_table(target: &mut Table, source: &Table) {
+    let stale: Vec<String> = target
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !source.contains_key(key))
+        .collect();
+    for key in stale {
+        target.remove(&key);
+    }
+
+    for (key, new_item) in source.iter() {
+        match (target.get_mut(key), new_item) {
+            (Some(Item::Table(table)), Item::Table(new_table)) => merge_table(table, new_table),
+            (Some(Item::ArrayOfTables(tables)), Item::ArrayOfTables(new_tables)) => {
+                while tables.len() > new_tables.len() {
+                    tables.remove(tables.len() - 1);
+                }
+                for (i, new_table) in new_tables.iter().enumerate() {
+                    match tables.get_mut(i) {
+                        Some(table) => merge_table(table, new_table),
+                        None => tables.push(new_table.clone()),
+                    }
+                }
+            }
+            (Some(Item::Value(value)), Item::Value(new_value)) => {
+                let decor = value.decor().clone();
+                *value = new_value.clone();
+                *value.decor_mut() = decor;
+            }
+            _ => {
+                target.insert(key, new_item.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CONFIG_VERSION;
+
+    fn merged(existing: &str, source: &str) -> String {
+        let mut document: DocumentMut = existing.parse().unwrap();
+        let source: DocumentMut = source.parse().unwrap();
+        merge_table(document.as_table_mut(), source.as_table());
+        document.to_string()
+    }
+
+    #[test]
+    fn toml_round_trip_keeps_the_config() {
+        let mut config = AppConfig::default();
+        config.profiles[0].sensitivity = 1.25;
+        let content = ConfigFormat::Toml.serialize(&config, None).unwrap();
+
+        let parsed = ConfigFormat::Toml.deserialize(&content).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), serde_json::to_value(&config).unwrap());
+        // TOML has no null, so unset options are left out of the document.
+        let document = ConfigFormat::Toml.document(&content).unwrap();
+        assert_eq!(document["version"], CONFIG_VERSION);
+        assert!(document.get("layer_shift_button").is_none());
+        assert_eq!(ConfigFormat::Toml.serialize(&parsed, Some(&content)).unwrap(), content);
+    }
+
+    #[test]
+    fn changed_values_keep_comments_and_key_order() {
+        let existing = "# Tablet settings\nb = 1 # kept\na = 2\n\n[table]\n# about x\nx = true\n";
+        let source = "a = 5\nb = 1\n\n[table]\nx = false\n";
+        assert_eq!(
+            merged(existing, source),
+            "# Tablet settings\nb = 1 # kept\na = 5\n\n[table]\n# about x\nx = false\n"
+        );
+    }
+
+    #[test]
+    fn removed_keys_are_deleted_and_new_ones_added() {
+        let existing = "a = 1\nold = 2\n\n[gone]\nx = 1\n\n[table]\nstale = true\ny = 1\n";
+        let source = "a = 1\nnew = 3\n\n[table]\ny = 1\n";
+        assert_eq!(merged(existing, source), "a = 1\nnew = 3\n\n[table]\ny = 1\n");
+    }
+
+    #[test]
+    fn saving_a_commented_config_keeps_the_comments() {
+        let mut config = AppConfig::default();
+        let existing = format!(
+            "# My tablet\n{}",
+            ConfigFormat::Toml.serialize(&config, None).unwrap()
+        );
+        config.profiles[0].sensitivity = 2.0;
+        let saved = ConfigFormat::Toml.serialize(&config, Some(&existing)).unwrap();
+        assert!(saved.starts_with("# My tablet\n"));
+        assert_eq!(ConfigFormat::Toml.deserialize(&saved).unwrap().profiles[0].sensitivity, 2.0);
+    }
+}
@@ -0,0 +1,173 @@
+use serde_json::{Map, Value};
+
+use super::{Profile, CONFIG_VERSION};
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` document to version `n + 2`.
+const MIGRATIONS: &[fn(Value) -> Value] = &[v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5];
+
+/// Version of a document that may predate the `version` field.
+pub fn detect_version(document: &Value) -> u32 {
+    match document.get("version").and_then(Value::as_u64) {
+        Some(version) => version as u32,
+        // Profiles were introduced before the version field.
+        None if document.get("profiles").is_some() => 2,
+        None => 1,
+    }
+}
+
+/// Applies every migration step from `version` up to `CONFIG_VERSION`.
+pub fn migrate(mut document: Value, version: u32) -> Value {
+    for step in &MIGRATIONS[(version.max(1) - 1) as usize..] {
+        document = step(document);
+    }
+    if let Value::Object(fields) = &mut document {
+        fields.insert("version".to_string(), CONFIG_VERSION.into());
+    }
+    document
+}
+
+/// Version 1 was a flat `{ pressure_threshold, sensitivity }` object; version 2
+/// moves those into a single profile with the default button maps.
+fn v1_to_v2(document: Value) -> Value {
+    let mut profile = Profile::default();
+    if let Some(threshold) = document.get("pressure_threshold").and_then(Value::as_u64) {
+        profile.pressure_threshold = Some(threshold.min(u16::MAX as u64) as u16);
+    }
+    if let Some(sensitivity) = document.get("sensitivity").and_then(Value::as_f64) {
+        profile.sensitivity = sensitivity as f32;
+    }
+
+    let mut fields = Map::new();
+    fields.insert("active_profile".to_string(), profile.name.clone().into());
+    fields.insert(
+        "profiles".to_string(),
+        Value::Array(vec![serde_json::to_value(profile).expect("profile serializes")]),
+    );
+    Value::Object(fields)
+}
+
+/// Version 3 moves the device-specific pressure threshold into `calibration`.
+/// Profiles left at the old built-in 510 now follow the calibrated value.
+fn v2_to_v3(mut document: Value) -> Value {
+    if let Some(Value::Array(profiles)) = document.get_mut("profiles") {
+        for profile in profiles.iter_mut().filter_map(Value::as_object_mut) {
+            if profile.get("pressure_threshold").and_then(Value::as_u64) == Some(510) {
+                profile.remove("pressure_threshold");
+            }
+        }
+    }
+    document
+}
+
+/// Version 4 turns the key lists of button maps into actions. Maps already in
+/// the new shape, such as those written by `v1_to_v2`, are left alone.
+fn v3_to_v4(mut document: Value) -> Value {
+    if let Some(Value::Array(profiles)) = document.get_mut("profiles") {
+        for profile in profiles.iter_mut().filter_map(Value::as_object_mut) {
+            for map in ["tablet_buttons", "pen_buttons", "media_buttons"] {
+                let Some(Value::Object(buttons)) = profile.get_mut(map) else {
+                    continue;
+                };
+                for action in buttons.values_mut() {
+                    if let Value::Array(keys) = action {
+                        *action = if keys.is_empty() {
+                            Value::from("None")
+                        } else {
+                            serde_json::json!({ "Keys": keys })
+                        };
+                    }
+                }
+            }
+        }
+    }
+    document
+}
+
+/// Version 5 makes `pressure_drift.rate` a share per second instead of per
+/// reading. Old values would be far off, so the default takes their place.
+fn v4_to_v5(mut document: Value) -> Value {
+    if let Some(Value::Object(drift)) = document.get_mut("pressure_drift") {
+        drift.remove("rate");
+    }
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Action, AppConfig, ConfigFormat, PressureDrift};
+
+    fn parse(content: &str) -> (AppConfig, Option<u32>) {
+        AppConfig::parse(content, ConfigFormat::Json).expect("fixture parses")
+    }
+
+    #[test]
+    fn flat_v1_file_becomes_a_v5_profile() {
+        let (config, old_version) = parse(r#"{ "pressure_threshold": 510, "sensitivity": 1.5 }"#);
+        assert_eq!(old_version, Some(1));
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.profiles.len(), 1);
+        let profile = &config.profiles[0];
+        assert_eq!(config.active_profile, profile.name);
+        assert_eq!(profile.sensitivity, 1.5);
+        // The old built-in threshold gives way to the calibrated one.
+        assert_eq!(profile.pressure_threshold, None);
+        assert_eq!(profile.tablet_buttons, Profile::default().tablet_buttons);
+        assert!(config.validate().is_valid());
+    }
+
+    #[test]
+    fn custom_v1_threshold_is_kept() {
+        let (config, _) = parse(r#"{ "pressure_threshold": 400, "sensitivity": 1.0 }"#);
+        assert_eq!(config.profiles[0].pressure_threshold, Some(400));
+    }
+
+    #[test]
+    fn v3_key_lists_and_drift_rate_are_migrated() {
+        let (config, old_version) = parse(
+            r#"{
+                "version": 3,
+                "profiles": [{
+                    "name": "Default",
+                    "pressure_threshold": 510,
+                    "tablet_buttons": { "1": ["KEY_LEFTCTRL", "KEY_Z"], "2": [] },
+                    "pen_buttons": { "1": ["BTN_STYLUS"] },
+                    "media_buttons": { "3": ["KEY_VOLUMEUP"] }
+                }],
+                "pressure_drift": { "enabled": false, "rate": 0.0005, "noise_band": 20 }
+            }"#,
+        );
+        assert_eq!(old_version, Some(3));
+        let profile = &config.profiles[0];
+        // Only v2_to_v3 drops a threshold of 510.
+        assert_eq!(profile.pressure_threshold, Some(510));
+        assert_eq!(profile.tablet_buttons[&1], Action::keys(&["KEY_LEFTCTRL", "KEY_Z"]));
+        assert_eq!(profile.tablet_buttons[&2], Action::None);
+        assert_eq!(profile.pen_buttons[&1], Action::keys(&["BTN_STYLUS"]));
+        assert_eq!(profile.media_buttons[&3], Action::keys(&["KEY_VOLUMEUP"]));
+
+        let drift = &config.pressure_drift;
+        assert_eq!(drift.rate, PressureDrift::default().rate);
+        assert!(!drift.enabled);
+        assert_eq!(drift.noise_band, 20);
+    }
+
+    #[test]
+    fn v2_threshold_of_510_is_dropped() {
+        let document = serde_json::json!({
+            "profiles": [{ "name": "A", "pressure_threshold": 510 }, { "name": "B", "pressure_threshold": 450 }]
+        });
+        assert_eq!(detect_version(&document), 2);
+        let migrated = migrate(document, 2);
+        assert_eq!(migrated["version"], CONFIG_VERSION);
+        assert!(migrated["profiles"][0].get("pressure_threshold").is_none());
+        assert_eq!(migrated["profiles"][1]["pressure_threshold"], 450);
+    }
+
+    #[test]
+    fn current_version_is_not_migrated() {
+        let content = serde_json::to_string(&AppConfig::default()).unwrap();
+        let (_, old_version) = parse(&content);
+        assert_eq!(old_version, None);
+    }
+}
@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Calibration, Profile, DEFAULT_SENSITIVITY, MAX_PRESSURE};
+
+/// Points `[input, output]` of a pressure curve, both in 0..=1, sorted by input.
+/// The first point is at input 0 and the last at input 1.
+pub type PressureCurve = Vec<[f32; 2]>;
+
+/// When the pen tip counts as down. Contact starts above the touch threshold
+/// and only ends `release_margin` below it, so a pressure hovering around the
+/// threshold doesn't toggle the tip.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TipSettings {
+    /// Raw pressure levels below the touch threshold at which contact ends.
+    pub release_margin: u16,
+    /// Contacts shorter than this never reach applications. 0 reports them at once.
+    pub min_contact_ms: u64,
+}
+
+impl Default for TipSettings {
+    fn default() -> Self {
+        Self {
+            release_margin: 40,
+            min_contact_ms: 0,
+        }
+    }
+}
+
+pub const CURVE_PRESETS: [(&str, &[[f32; 2]]); 4] = [
+    ("Linear", &[[0.0, 0.0], [1.0, 1.0]]),
+    ("Soft start", &[[0.0, 0.0], [0.4, 0.15], [1.0, 1.0]]),
+    ("Firm", &[[0.0, 0.0], [0.3, 0.5], [1.0, 1.0]]),
+    ("Hard end", &[[0.0, 0.0], [0.75, 1.0], [1.0, 1.0]]),
+];
+
+pub fn linear_curve() -> PressureCurve {
+    CURVE_PRESETS[0].1.to_vec()
+}
+
+/// Piecewise-linear interpolation of `curve` at `input`.
+pub fn evaluate_curve(curve: &[[f32; 2]], input: f32) -> f32 {
+    let Some(first) = curve.first() else {
+        return input;
+    };
+    if input <= first[0] {
+        return first[1];
+    }
+    for pair in curve.windows(2) {
+        let ([x0, y0], [x1, y1]) = (pair[0], pair[1]);
+        if input <= x1 {
+            let t = if x1 > x0 { (input - x0) / (x1 - x0) } else { 1.0 };
+            return y0 + (y1 - y0) * t;
+        }
+    }
+    curve[curve.len() - 1][1]
+}
+
+impl Profile {
+    pub fn threshold(&self, calibration: &Calibration) -> u16 {
+        self.pressure_threshold.unwrap_or(calibration.pressure_threshold)
+    }
+
+    /// Position on the pressure curve for a raw reading: the threshold and
+    /// sensitivity scale it to 0..=1. `None` while the pen is below the threshold.
+    pub fn curve_input(&self, calibration: &Calibration, raw_pressure: i32) -> Option<f32> {
+        let val = calibration.pressure_idle - raw_pressure;
+        if val <= self.threshold(calibration) as i32 {
+            return None;
+        }
+        let full = calibration.pressure_range.max(1) as f32 * DEFAULT_SENSITIVITY;
+        Some((val as f32 * self.sensitivity / full).clamp(0.0, 1.0))
+    }
+
+    /// Pressure sent to the virtual pen, 0..=MAX_PRESSURE.
+    pub fn map_pressure(&self, calibration: &Calibration, raw_pressure: i32) -> i32 {
+        match self.curve_input(calibration, raw_pressure) {
+            Some(input) => (evaluate_curve(&self.pressure_curve, input) * MAX_PRESSURE as f32) as i32,
+            None => 0,
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use evdev::Key;
+
+use super::{
+    Action, AppConfig, ButtonMap, Calibration, Profile, RepeatMode, PEN_BUTTON_IDS,
+    SENSITIVITY_RANGE, TABLET_BUTTON_IDS, TABLET_MAX_X, TABLET_MAX_Y,
+};
+
+/// Problems found in a config. Errors make the config unusable, warnings are
+/// settings that will be ignored.
+#[derive(Debug, Default)]
+pub struct Validation {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl Validation {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl AppConfig {
+    pub fn validate(&self) -> Validation {
+        let mut result = Validation::default();
+
+        validate_calibration(&self.calibration, &mut result);
+        let drift = &self.pressure_drift;
+        if drift.max_drift < 0 || drift.noise_band < 0 || !(0.0..=1.0).contains(&drift.rate) {
+            result.errors.push(format!(
+                "pressure_drift: max_drift {} and noise_band {} must not be negative and rate {} must be in 0..=1",
+                drift.max_drift, drift.noise_band, drift.rate
+            ));
+        }
+
+        let timing = self.button_timing;
+        if timing.hold_ms == 0 || timing.double_tap_ms == 0 {
+            result.errors.push(format!(
+                "button_timing: hold_ms {} and double_tap_ms {} must be positive",
+                timing.hold_ms, timing.double_tap_ms
+            ));
+        }
+
+        let repeat = self.key_repeat;
+        if repeat.mode == RepeatMode::Driver && repeat.interval_ms == 0 {
+            result.errors.push("key_repeat: interval_ms must be positive".to_string());
+        }
+
+        let mut names = HashSet::new();
+        for profile in &self.profiles {
+            if !names.insert(profile.name.as_str()) {
+                result.errors.push(format!("profile \"{}\" is defined twice", profile.name));
+            }
+            validate_profile(profile, self, &mut result);
+        }
+
+        if !names.contains(self.active_profile.as_str()) {
+            result.warnings.push(format!(
+                "active profile \"{}\" does not exist, using \"{}\"",
+                self.active_profile,
+                self.active().name
+            ));
+        }
+        if let Some(id) = self.profile_cycle_button
+            && !TABLET_BUTTON_IDS.contains(&id)
+        {
+            result.warnings.push(format!("profile_cycle_button: tablet has no button {}", id));
+        }
+        if let Some(id) = self.layer_shift_button {
+            if !TABLET_BUTTON_IDS.contains(&id) {
+                result.warnings.push(format!("layer_shift_button: tablet has no button {}", id));
+            }
+            if self.profile_cycle_button == Some(id) {
+                result.errors.push(format!(
+                    "button {} cannot be both profile_cycle_button and layer_shift_button",
+                    id
+                ));
+            }
+        }
+        for rule in &self.profile_rules {
+            if !names.contains(rule.profile.as_str()) {
+                result.warnings.push(format!("profile rule uses unknown profile \"{}\"", rule.profile));
+            }
+            if rule.window_class.is_none() && rule.process_name.is_none() {
+                result.warnings.push(format!(
+                    "profile rule for \"{}\" has neither window_class nor process_name",
+                    rule.profile
+                ));
+            }
+        }
+
+        result
+    }
+}
+
+fn validate_calibration(calibration: &Calibration, result: &mut Validation) {
+    let c = calibration;
+    if c.pressure_idle <= 0 || c.pressure_range <= 0
+        || c.pressure_threshold as i32 >= c.pressure_idle
+    {
+        result.errors.push(format!(
+            "calibration: pressure_idle {}, pressure_threshold {} and pressure_range {} must be positive, with the threshold below the idle value",
+            c.pressure_idle, c.pressure_threshold, c.pressure_range
+        ));
+    }
+    if c.x_min >= c.x_max || c.y_min >= c.y_max {
+        result.errors.push(format!(
+            "calibration: extents {}..{} x {}..{} are empty",
+            c.x_min, c.x_max, c.y_min, c.y_max
+        ));
+    }
+    let zones_in_order = c.media_zones.windows(2).all(|pair| pair[0].x_max <= pair[1].x_min);
+    let zones_valid = c.media_zones.iter().all(|zone| {
+        zone.x_min >= 0 && zone.x_max <= TABLET_MAX_X && zone.x_max - zone.x_min > c.media_dead_band
+    });
+    if c.media_zones.len() > u8::MAX as usize || !zones_in_order || !zones_valid || c.media_dead_band < 0 {
+        result.errors.push(format!(
+            "calibration: media_zones must lie within 0..={} from left to right without overlapping, each wider than media_dead_band {}",
+            TABLET_MAX_X, c.media_dead_band
+        ));
+    }
+    if c.media_strip_y > c.y_min {
+        result.errors.push(format!(
+            "calibration: media_strip_y {} is inside the drawing area (y_min {})",
+            c.media_strip_y, c.y_min
+        ));
+    }
+}
+
+fn validate_profile(profile: &Profile, config: &AppConfig, result: &mut Validation) {
+    let name = &profile.name;
+    let calibration = &config.calibration;
+
+    if config.tip.release_margin >= profile.threshold(calibration) {
+        result.errors.push(format!(
+            "{}: tip release_margin {} must be below the touch threshold {}, or the tip never lifts after the first touch",
+            name, config.tip.release_margin, profile.threshold(calibration)
+        ));
+    }
+
+    if let Some(threshold) = profile.pressure_threshold
+        && threshold as i32 >= calibration.pressure_idle
+    {
+        result.errors.push(format!(
+            "{}: pressure_threshold {} is above the raw pressure range (0..{})",
+            name, threshold, calibration.pressure_idle
+        ));
+    }
+    if !SENSITIVITY_RANGE.contains(&profile.sensitivity) {
+        result.errors.push(format!(
+            "{}: sensitivity {} is outside {}..={}",
+            name, profile.sensitivity, SENSITIVITY_RANGE.start(), SENSITIVITY_RANGE.end()
+        ));
+    }
+    let curve = &profile.pressure_curve;
+    let in_range = curve.iter().flatten().all(|v| (0.0..=1.0).contains(v));
+    let sorted = curve.windows(2).all(|pair| pair[0][0] <= pair[1][0]);
+    let spans = curve.first().is_some_and(|p| p[0] == 0.0) && curve.last().is_some_and(|p| p[0] == 1.0);
+    if curve.len() < 2 || !in_range || !sorted || !spans {
+        result.errors.push(format!(
+            "{}: pressure_curve needs points in 0..=1, sorted by input, from input 0 to input 1",
+            name
+        ));
+    }
+    if !(0.0..=1.0).contains(&profile.smoothing) {
+        result.errors.push(format!("{}: smoothing {} is outside 0..=1", name, profile.smoothing));
+    }
+
+    let area = profile.area;
+    if area.x_min < 0 || area.y_min < 0 || area.x_max > TABLET_MAX_X || area.y_max > TABLET_MAX_Y
+        || area.x_min >= area.x_max || area.y_min >= area.y_max
+    {
+        result.errors.push(format!(
+            "{}: area {}..{} x {}..{} is empty or outside the tablet (0..{} x 0..{})",
+            name, area.x_min, area.x_max, area.y_min, area.y_max, TABLET_MAX_X, TABLET_MAX_Y
+        ));
+    }
+
+    let output = profile.output;
+    let fits = |start: f32, size: f32| start >= 0.0 && size > 0.0 && start + size <= 1.0 + f32::EPSILON;
+    if !fits(output.x, output.width) || !fits(output.y, output.height) {
+        result.errors.push(format!(
+            "{}: output {}+{} x {}+{} is empty or outside the desktop (0..=1)",
+            name, output.x, output.width, output.y, output.height
+        ));
+    }
+
+    if profile.media_slider.step <= 0 {
+        result.errors.push(format!(
+            "{}: media_slider step {} must be positive",
+            name, profile.media_slider.step
+        ));
+    }
+    if profile.dead_zone < 0 {
+        result.errors.push(format!("{}: dead_zone {} must not be negative", name, profile.dead_zone));
+    }
+    if profile.drag.step <= 0 {
+        result.errors.push(format!("{}: drag step {} must be positive", name, profile.drag.step));
+    }
+
+    let media_ids: Vec<u8> = (0..calibration.media_zones.len() as u8).collect();
+    let maps: [(&str, &ButtonMap, &[u8]); 7] = [
+        ("tablet_buttons", &profile.tablet_buttons, &TABLET_BUTTON_IDS),
+        ("tablet_hold", &profile.tablet_hold, &TABLET_BUTTON_IDS),
+        ("tablet_double_tap", &profile.tablet_double_tap, &TABLET_BUTTON_IDS),
+        ("pen_buttons", &profile.pen_buttons, &PEN_BUTTON_IDS),
+        ("media_buttons", &profile.media_buttons, &media_ids),
+        ("shifted_tablet_buttons", &profile.shifted_tablet_buttons, &TABLET_BUTTON_IDS),
+        ("shifted_media_buttons", &profile.shifted_media_buttons, &media_ids),
+    ];
+    for (map_name, map, ids) in maps {
+        validate_buttons(name, map_name, map, ids, config, result);
+    }
+}
+
+fn validate_buttons(
+    profile: &str,
+    map_name: &str,
+    map: &ButtonMap,
+    ids: &[u8],
+    config: &AppConfig,
+    result: &mut Validation,
+) {
+    for (id, action) in map {
+        if !ids.contains(id) {
+            result.warnings.push(format!("{}: {} has no button {}", profile, map_name, id));
+        }
+        if let Action::SwitchProfile(target) = action
+            && !config.profiles.iter().any(|p| &p.name == target)
+        {
+            result.warnings.push(format!(
+                "{}: {} button {} switches to unknown profile \"{}\"",
+                profile, map_name, id, target
+            ));
+        }
+        if let Action::RepeatKeys(repeated) = action
+            && repeated.interval_ms == 0
+        {
+            result.errors.push(format!(
+                "{}: {} button {}: repeat interval_ms must be positive",
+                profile, map_name, id
+            ));
+        }
+        for key in action.key_names() {
+            if Key::from_str(key).is_err() {
+                result.errors.push(format!(
+                    "{}: {} button {}: unknown key name \"{}\"",
+                    profile, map_name, id, key
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::AppConfig;
+use crate::physical_device::UsbLocation;
+
+/// One pen report as the driver saw it, before and after processing.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct PenSample {
+    pub raw_x: i32,
+    pub raw_y: i32,
+    pub raw_pressure: i32,
+    /// Position and pressure sent to the virtual pen.
+    pub x: i32,
+    pub y: i32,
+    pub pressure: i32,
+    pub media_strip: bool,
+}
+
+/// The tablet the driver is talking to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DeviceInfo {
+    pub location: UsbLocation,
+    pub endpoint: u8,
+}
+
+/// Health of the driver, sent periodically.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DriverStatus {
+    pub device: Option<DeviceInfo>,
+    /// Reports read from the tablet per second.
+    pub report_rate: f32,
+    /// Correction of the pen's idle pressure, in raw units.
+    pub pressure_drift: i32,
+    /// Profile the driver is using. Cycling or switching from the tablet only
+    /// changes it in memory, so it can differ from the saved one.
+    pub active_profile: String,
+    pub last_error: Option<String>,
+}
+
+/// Messages the driver sends to every connected client, one JSON object per line.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum DriverEvent {
+    ConfigIssue { error: bool, message: String },
+    PenSample(PenSample),
+    Status(DriverStatus),
+}
+
+/// Messages clients send to the driver, one JSON object per line.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ClientRequest {
+    /// Use these settings until reverted, without touching the config file.
+    Preview(Box<AppConfig>),
+    /// Go back to the settings in the config file.
+    Revert,
+}
+
+pub fn socket_path() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+        .join("v1060p-driver.sock")
+}
+
+/// Local control channel of the running driver. Cloning shares the same socket.
+#[derive(Clone, Default)]
+pub struct ControlServer {
+    clients: Arc<Mutex<Vec<SyncSender<String>>>>,
+}
+
+impl ControlServer {
+    /// Messages queued for a client that stops reading are dropped past this.
+    const CLIENT_QUEUE: usize = 256;
+
+    /// Requests from clients are passed to `requests`. A client that goes away
+    /// while previewing settings is treated as if it had sent `Revert`.
+    pub fn start(requests: Sender<ClientRequest>) -> io::Result<Self> {
+        let path = socket_path();
+        // A socket left behind by a crashed driver would make bind fail.
+        if UnixStream::connect(&path).is_err() {
+            let _ = fs::remove_file(&path);
+        }
+        let listener = UnixListener::bind(&path)?;
+        // Clients can change the settings, so only the driver's user may connect.
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        let server = Self::default();
+
+        let clients = server.clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (sender, receiver) = mpsc::sync_channel::<String>(Self::CLIENT_QUEUE);
+                clients.lock().unwrap().push(sender);
+                if let Ok(reader) = stream.try_clone() {
+                    let requests = requests.clone();
+                    thread::spawn(move || Self::read_requests(reader, requests));
+                }
+                thread::spawn(move || {
+                    let mut stream = stream;
+                    for line in receiver {
+                        if stream.write_all(line.as_bytes()).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(server)
+    }
+
+    fn read_requests(stream: UnixStream, requests: Sender<ClientRequest>) {
+        let mut previewing = false;
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let request = match serde_json::from_str(&line) {
+                Ok(request) => request,
+                Err(e) => {
+                    eprintln!("Ignoring invalid control request: {}", e);
+                    continue;
+                }
+            };
+            previewing = matches!(request, ClientRequest::Preview(_));
+            if requests.send(request).is_err() {
+                return;
+            }
+        }
+        if previewing {
+            let _ = requests.send(ClientRequest::Revert);
+        }
+    }
+
+    pub fn has_clients(&self) -> bool {
+        !self.clients.lock().unwrap().is_empty()
+    }
+
+    pub fn broadcast(&self, event: &DriverEvent) {
+        let Ok(mut line) = serde_json::to_string(event) else {
+            return;
+        };
+        line.push('\n');
+        self.clients.lock().unwrap().retain(|client| {
+            !matches!(client.try_send(line.clone()), Err(TrySendError::Disconnected(_)))
+        });
+    }
+}
+
+/// Connection from a tool such as the GUI to the running driver.
+pub struct ControlClient {
+    stream: UnixStream,
+    events: Receiver<DriverEvent>,
+}
+
+impl ControlClient {
+    pub fn connect() -> io::Result<Self> {
+        let stream = UnixStream::connect(socket_path())?;
+        let reader = stream.try_clone()?;
+        let (sender, events) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if let Ok(event) = serde_json::from_str(&line)
+                    && sender.send(event).is_err()
+                {
+                    break;
+                }
+            }
+        });
+        Ok(Self { stream, events })
+    }
+
+    pub fn send(&self, request: &ClientRequest) -> io::Result<()> {
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        (&self.stream).write_all(line.as_bytes())
+    }
+
+    /// Events received since the last call. `Err` once the driver has gone away.
+    pub fn poll(&self) -> Result<Vec<DriverEvent>, TryRecvError> {
+        let mut events = Vec::new();
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => events.push(event),
+                Err(TryRecvError::Empty) => return Ok(events),
+                Err(TryRecvError::Disconnected) => return Err(TryRecvError::Disconnected),
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::time::Instant;
+
+use crate::config::{Calibration, PressureDrift};
+
+/// Where the idle pressure of the pen currently is, relative to the calibrated value.
+#[derive(Default)]
+pub struct BaselineTracker {
+    offset: f32,
+    /// Time of the last reading, which sets how far the next one moves the baseline.
+    last_reading: Option<Instant>,
+    /// The offset is larger than the touch threshold; logged once until it shrinks again.
+    beyond_threshold: bool,
+    at_limit: bool,
+}
+
+impl BaselineTracker {
+    /// Raw pressure corrected for the drift seen so far. Hover readings within
+    /// `noise_band` of the baseline move it toward themselves by `rate` per
+    /// second; anything further away may be the start of a press.
+    pub fn correct(
+        &mut self,
+        raw_pressure: i32,
+        calibration: &Calibration,
+        threshold: u16,
+        settings: &PressureDrift,
+        now: Instant,
+    ) -> i32 {
+        if !settings.enabled {
+            self.offset = 0.0;
+            self.last_reading = None;
+            return raw_pressure;
+        }
+
+        let elapsed = self.last_reading.map_or(0.0, |last| (now - last).as_secs_f32());
+        self.last_reading = Some(now);
+
+        let deviation = (raw_pressure - calibration.pressure_idle) as f32 - self.offset;
+        if deviation.abs() <= settings.noise_band as f32 {
+            let limit = settings.max_drift as f32;
+            let offset = self.offset + deviation * (settings.rate * elapsed).min(1.0);
+            self.offset = offset.clamp(-limit, limit);
+            self.log_changes(offset.abs() > limit, threshold, settings.max_drift);
+        }
+        raw_pressure - self.offset()
+    }
+
+    pub fn offset(&self) -> i32 {
+        self.offset.round() as i32
+    }
+
+    fn log_changes(&mut self, at_limit: bool, threshold: u16, max_drift: i32) {
+        let beyond_threshold = self.offset.abs() > threshold as f32;
+        if beyond_threshold && !self.beyond_threshold {
+            println!(
+                "Pressure baseline drifted by {}, more than the touch threshold of {} absorbs. Consider recalibrating.",
+                self.offset(), threshold
+            );
+        }
+        // Some slack so a value hovering around the threshold isn't logged over and over.
+        self.beyond_threshold = beyond_threshold
+            || (self.beyond_threshold && self.offset.abs() > threshold as f32 / 2.0);
+
+        if at_limit && !self.at_limit {
+            eprintln!(
+                "Pressure baseline drifted beyond max_drift ({}); touches may be missed or phantom. Recalibrate the pen.",
+                max_drift
+            );
+        }
+        self.at_limit = at_limit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Readings every 5 ms, as the tablet reports them.
+    const REPORT_INTERVAL: Duration = Duration::from_millis(5);
+
+    /// Feeds `readings` one report apart and returns the resulting offset.
+    fn track(tracker: &mut BaselineTracker, readings: impl IntoIterator<Item = i32>) -> i32 {
+        let calibration = Calibration::default();
+        let settings = PressureDrift::default();
+        let mut now = Instant::now();
+        for raw in readings {
+            tracker.correct(raw, &calibration, calibration.pressure_threshold, &settings, now);
+            now += REPORT_INTERVAL;
+        }
+        tracker.offset()
+    }
+
+    #[test]
+    fn follows_drift_within_the_noise_band() {
+        let idle = Calibration::default().pressure_idle;
+        let mut tracker = BaselineTracker::default();
+        // Two minutes of hovering 30 below the calibrated idle pressure.
+        let offset = track(&mut tracker, std::iter::repeat_n(idle - 30, 24_000));
+        assert_eq!(offset, -30);
+    }
+
+    #[test]
+    fn slow_press_does_not_move_the_baseline() {
+        let calibration = Calibration::default();
+        let idle = calibration.pressure_idle;
+        let threshold = calibration.pressure_threshold as i32;
+        let mut tracker = BaselineTracker::default();
+        // Five seconds from idle to just short of a touch.
+        let ramp = (0..1000).map(|i| idle - i * (threshold - 1) / 1000);
+        assert_eq!(track(&mut tracker, ramp), 0);
+    }
+
+    #[test]
+    fn readings_beyond_the_noise_band_are_ignored() {
+        let idle = Calibration::default().pressure_idle;
+        let mut tracker = BaselineTracker::default();
+        assert_eq!(track(&mut tracker, std::iter::repeat_n(idle - 100, 12_000)), 0);
+        assert_eq!(track(&mut tracker, std::iter::repeat_n(idle + 100, 12_000)), 0);
+    }
+}
@@ -0,0 +1,491 @@
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, Window};
+use x11rb::rust_connection::RustConnection;
+
+use crate::config::{AppConfig, FocusDetector, ProfileRule};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FocusedWindow {
+    pub class: Option<String>,
+    pub process: Option<String>,
+}
+
+impl FocusedWindow {
+    fn from_pid(class: Option<String>, pid: Option<u32>) -> Self {
+        Self {
+            class,
+            process: pid.and_then(process_name),
+        }
+    }
+}
+
+/// Something that can tell which application currently has keyboard focus.
+pub trait FocusSource: Send {
+    fn name(&self) -> &'static str;
+
+    /// `Ok(None)` means nothing is focused, `Err` that the source stopped working.
+    fn focused(&mut self) -> Result<Option<FocusedWindow>, String>;
+}
+
+fn process_name(pid: u32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{}/comm", pid))
+        .ok()
+        .map(|comm| comm.trim().to_string())
+}
+
+pub struct X11Source {
+    conn: RustConnection,
+    root: Window,
+    net_active_window: Atom,
+    net_wm_pid: Atom,
+}
+
+impl X11Source {
+    pub fn connect() -> Result<Self, String> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+        let root = conn.setup().roots[screen_num].root;
+        let net_active_window = Self::atom(&conn, b"_NET_ACTIVE_WINDOW")?;
+        let net_wm_pid = Self::atom(&conn, b"_NET_WM_PID")?;
+        Ok(Self {
+            conn,
+            root,
+            net_active_window,
+            net_wm_pid,
+        })
+    }
+
+    fn atom(conn: &RustConnection, name: &[u8]) -> Result<Atom, String> {
+        conn.intern_atom(false, name)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map(|reply| reply.atom)
+            .map_err(|e| e.to_string())
+    }
+
+    fn property(&self, window: Window, property: Atom, kind: AtomEnum, len: u32)
+        -> Result<x11rb::protocol::xproto::GetPropertyReply, String>
+    {
+        self.conn
+            .get_property(false, window, property, kind, 0, len)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl FocusSource for X11Source {
+    fn name(&self) -> &'static str {
+        "X11"
+    }
+
+    fn focused(&mut self) -> Result<Option<FocusedWindow>, String> {
+        let active = self.property(self.root, self.net_active_window, AtomEnum::WINDOW, 1)?;
+        let Some(window) = active.value32().and_then(|mut v| v.next()).filter(|&w| w != 0) else {
+            return Ok(None);
+        };
+
+        // WM_CLASS holds "instance\0class\0"; the class part is what users know.
+        let wm_class = self.property(window, AtomEnum::WM_CLASS.into(), AtomEnum::STRING, 256)?;
+        let class = wm_class
+            .value
+            .split(|&b| b == 0)
+            .filter(|part| !part.is_empty())
+            .nth(1)
+            .map(|part| String::from_utf8_lossy(part).into_owned());
+        let pid = self
+            .property(window, self.net_wm_pid, AtomEnum::CARDINAL, 1)?
+            .value32()
+            .and_then(|mut v| v.next());
+
+        Ok(Some(FocusedWindow::from_pid(class, pid)))
+    }
+}
+
+/// Sway and i3 share the same IPC protocol, only the socket variable differs.
+pub struct SwayIpcSource {
+    socket_path: String,
+}
+
+impl SwayIpcSource {
+    const MAGIC: &'static [u8] = b"i3-ipc";
+    const GET_TREE: u32 = 4;
+
+    pub fn from_env() -> Option<Self> {
+        env::var("SWAYSOCK")
+            .or_else(|_| env::var("I3SOCK"))
+            .ok()
+            .map(|socket_path| Self { socket_path })
+    }
+
+    fn get_tree(&self) -> Result<Value, String> {
+        let mut stream = UnixStream::connect(&self.socket_path).map_err(|e| e.to_string())?;
+        stream.set_read_timeout(Some(Duration::from_secs(1))).map_err(|e| e.to_string())?;
+
+        let mut request = Self::MAGIC.to_vec();
+        request.extend_from_slice(&0u32.to_ne_bytes());
+        request.extend_from_slice(&Self::GET_TREE.to_ne_bytes());
+        stream.write_all(&request).map_err(|e| e.to_string())?;
+
+        let mut header = [0u8; 14];
+        stream.read_exact(&mut header).map_err(|e| e.to_string())?;
+        if &header[..6] != Self::MAGIC {
+            return Err("invalid IPC reply".to_string());
+        }
+        let len = u32::from_ne_bytes(header[6..10].try_into().unwrap()) as usize;
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).map_err(|e| e.to_string())?;
+        serde_json::from_slice(&payload).map_err(|e| e.to_string())
+    }
+
+    fn find_focused(node: &Value) -> Option<&Value> {
+        if node["focused"].as_bool() == Some(true) {
+            return Some(node);
+        }
+        ["nodes", "floating_nodes"]
+            .iter()
+            .filter_map(|key| node[key].as_array())
+            .flatten()
+            .find_map(Self::find_focused)
+    }
+}
+
+impl FocusSource for SwayIpcSource {
+    fn name(&self) -> &'static str {
+        "Sway/i3 IPC"
+    }
+
+    fn focused(&mut self) -> Result<Option<FocusedWindow>, String> {
+        let tree = self.get_tree()?;
+        let Some(node) = Self::find_focused(&tree) else {
+            return Ok(None);
+        };
+        // Wayland clients report an app_id, XWayland and i3 clients a WM_CLASS.
+        let class = node["app_id"]
+            .as_str()
+            .or_else(|| node["window_properties"]["class"].as_str())
+            .map(str::to_string);
+        let pid = node["pid"].as_u64().map(|pid| pid as u32);
+        Ok(Some(FocusedWindow::from_pid(class, pid)))
+    }
+}
+
+/// GNOME Shell does not expose the focused window by itself; this talks to the
+/// "Focused Window D-Bus" shell extension through `gdbus`.
+pub struct GnomeDbusSource;
+
+impl FocusSource for GnomeDbusSource {
+    fn name(&self) -> &'static str {
+        "GNOME D-Bus"
+    }
+
+    fn focused(&mut self) -> Result<Option<FocusedWindow>, String> {
+        let reply = run_helper("gdbus", &[
+            "call", "--session",
+            "--dest", "org.gnome.Shell",
+            "--object-path", "/org/gnome/shell/extensions/FocusedWindow",
+            "--method", "org.gnome.shell.extensions.FocusedWindow.Get",
+        ])?;
+        // gdbus prints the returned string as a GVariant tuple: ('{"wm_class": ...}',)
+        let json = reply
+            .trim()
+            .trim_start_matches("('")
+            .trim_end_matches("',)");
+        let window: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let class = window["wm_class"].as_str().map(str::to_string);
+        let pid = window["pid"].as_u64().map(|pid| pid as u32);
+        Ok(Some(FocusedWindow::from_pid(class, pid)))
+    }
+}
+
+/// KWin only answers through its scripting D-Bus API, wrapped by the `kdotool` helper.
+pub struct KdeDbusSource;
+
+impl FocusSource for KdeDbusSource {
+    fn name(&self) -> &'static str {
+        "KDE D-Bus"
+    }
+
+    fn focused(&mut self) -> Result<Option<FocusedWindow>, String> {
+        let reply = run_helper("kdotool", &[
+            "getactivewindow", "getwindowclassname", "getwindowpid",
+        ])?;
+        let mut lines = reply.lines().map(str::trim).filter(|l| !l.is_empty());
+        let class = lines.next().map(str::to_string);
+        let pid = lines.next().and_then(|pid| pid.parse().ok());
+        Ok(Some(FocusedWindow::from_pid(class, pid)))
+    }
+}
+
+fn run_helper(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("{}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!("{}: {}", program, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Picks the detector for the running session, or the one forced in the config.
+pub fn detect_source(detector: FocusDetector) -> Option<Box<dyn FocusSource>> {
+    let desktop = env::var("XDG_CURRENT_DESKTOP").unwrap_or_default().to_uppercase();
+    let wayland = env::var_os("WAYLAND_DISPLAY").is_some();
+
+    match detector {
+        FocusDetector::Disabled => None,
+        FocusDetector::X11 => X11Source::connect().ok().map(|s| Box::new(s) as _),
+        FocusDetector::SwayIpc => SwayIpcSource::from_env().map(|s| Box::new(s) as _),
+        FocusDetector::Gnome => Some(Box::new(GnomeDbusSource)),
+        FocusDetector::Kde => Some(Box::new(KdeDbusSource)),
+        FocusDetector::Auto => {
+            if let Some(source) = SwayIpcSource::from_env() {
+                Some(Box::new(source))
+            } else if wayland && desktop.contains("GNOME") {
+                Some(Box::new(GnomeDbusSource))
+            } else if wayland && desktop.contains("KDE") {
+                Some(Box::new(KdeDbusSource))
+            } else {
+                X11Source::connect().ok().map(|s| Box::new(s) as _)
+            }
+        }
+    }
+}
+
+fn matches(rule: &ProfileRule, window: &FocusedWindow) -> bool {
+    let same = |pattern: &Option<String>, value: &Option<String>| match (pattern, value) {
+        (Some(pattern), Some(value)) => pattern.eq_ignore_ascii_case(value),
+        _ => false,
+    };
+    same(&rule.window_class, &window.class) || same(&rule.process_name, &window.process)
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Longest wait before a failed focus detector is tried again.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Applies `profile_rules` to focus changes. Whatever profile was active while no
+/// rule matched is treated as the manual choice and restored when focus leaves
+/// a matched application.
+pub struct ProfileSwitcher {
+    source: Box<dyn FocusSource>,
+    last_window: Option<FocusedWindow>,
+    manual_profile: Option<String>,
+    auto_profile: Option<String>,
+    /// The config was replaced, so the rule of the focused window applies again.
+    config_replaced: bool,
+}
+
+impl ProfileSwitcher {
+    pub fn new(source: Box<dyn FocusSource>) -> Self {
+        Self {
+            source,
+            last_window: None,
+            manual_profile: None,
+            auto_profile: None,
+            config_replaced: false,
+        }
+    }
+
+    /// A reload or preview brings the `active_profile` of the file, which
+    /// becomes the manual choice. The focused window's rule is applied again
+    /// at the next poll.
+    pub fn config_replaced(&mut self) {
+        self.config_replaced = true;
+    }
+
+    pub fn source_name(&self) -> &'static str {
+        self.source.name()
+    }
+
+    pub fn poll(&mut self, config: &RwLock<AppConfig>) -> Result<(), String> {
+        let window = self.source.focused()?;
+        let replaced = std::mem::take(&mut self.config_replaced);
+        if window == self.last_window && !replaced {
+            return Ok(());
+        }
+        self.last_window = window.clone();
+
+        let mut config = config.write().unwrap();
+        if replaced || self.auto_profile.as_ref() != Some(&config.active_profile) {
+            // Changed by hand (GUI, cycle button, reload) since the last automatic switch.
+            self.manual_profile = Some(config.active_profile.clone());
+            self.auto_profile = None;
+        }
+
+        let rule_profile = window.as_ref().and_then(|window| {
+            config
+                .profile_rules
+                .iter()
+                .find(|rule| matches(rule, window))
+                .map(|rule| rule.profile.clone())
+        });
+        let target = match rule_profile {
+            Some(profile) if config.profiles.iter().any(|p| p.name == profile) => {
+                self.auto_profile = Some(profile.clone());
+                profile
+            }
+            _ => {
+                self.auto_profile = None;
+                match &self.manual_profile {
+                    Some(profile) => profile.clone(),
+                    None => return Ok(()),
+                }
+            }
+        };
+
+        if config.active_profile != target {
+            println!("Focus changed, switching to profile \"{}\"", target);
+            config.active_profile = target;
+        }
+        Ok(())
+    }
+}
+
+/// Doubles the delay before the next attempt, up to `MAX_RETRY_DELAY`, and
+/// returns when that attempt is due.
+fn back_off(retry_delay: &mut Option<Duration>) -> Option<Instant> {
+    let delay = retry_delay.map_or(Duration::from_secs(1), |d| (d * 2).min(MAX_RETRY_DELAY));
+    *retry_delay = Some(delay);
+    Some(Instant::now() + delay)
+}
+
+/// Runs `profile_rules` in the background. The detector is picked again when
+/// `focus_detector` changes, and after a failure once a growing delay has passed.
+/// Set the returned flag after replacing the config.
+pub fn spawn_profile_switcher(config: Arc<RwLock<AppConfig>>) -> Arc<AtomicBool> {
+    let replaced = Arc::new(AtomicBool::new(false));
+    let notice = replaced.clone();
+
+    thread::spawn(move || {
+        let mut switcher: Option<ProfileSwitcher> = None;
+        let mut detector = None;
+        let mut retry_at = Some(Instant::now());
+        // Set while recovering from a failure, doubled on each further one.
+        let mut retry_delay: Option<Duration> = None;
+        loop {
+            let wanted = config.read().unwrap().focus_detector;
+            if detector != Some(wanted) {
+                detector = Some(wanted);
+                switcher = None;
+                retry_at = Some(Instant::now());
+                retry_delay = None;
+            }
+
+            if switcher.is_none() && retry_at.is_some_and(|at| Instant::now() >= at) {
+                retry_at = None;
+                match detect_source(wanted) {
+                    Some(source) => {
+                        println!("Per-application profiles enabled ({}).", source.name());
+                        switcher = Some(ProfileSwitcher::new(source));
+                    }
+                    None if retry_delay.is_some() => retry_at = back_off(&mut retry_delay),
+                    None => println!("No focus detector available, profiles are switched manually."),
+                }
+            }
+
+            if let Some(active) = &mut switcher {
+                if replaced.swap(false, Ordering::Relaxed) {
+                    active.config_replaced();
+                }
+                match active.poll(&config) {
+                    Ok(()) => retry_delay = None,
+                    Err(e) => {
+                        retry_at = back_off(&mut retry_delay);
+                        eprintln!("Focus detector {} failed: {}. Retrying in {} s.",
+                            active.source_name(), e, retry_delay.unwrap_or_default().as_secs());
+                        switcher = None;
+                    }
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+    notice
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Profile;
+    use std::sync::Mutex;
+
+    struct FakeSource(Arc<Mutex<Option<FocusedWindow>>>);
+
+    impl FocusSource for FakeSource {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn focused(&mut self) -> Result<Option<FocusedWindow>, String> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+    }
+
+    fn window(class: &str) -> Option<FocusedWindow> {
+        Some(FocusedWindow {
+            class: Some(class.to_string()),
+            process: None,
+        })
+    }
+
+    fn config_with_krita_rule() -> AppConfig {
+        let mut config = AppConfig::default();
+        config.profiles.push(Profile::new("Krita"));
+        config.profile_rules.push(ProfileRule {
+            profile: "Krita".to_string(),
+            window_class: Some("krita".to_string()),
+            process_name: None,
+        });
+        config
+    }
+
+    #[test]
+    fn switches_by_rule_and_restores_manual_profile() {
+        let config = RwLock::new(config_with_krita_rule());
+        let focus = Arc::new(Mutex::new(window("firefox")));
+        let mut switcher = ProfileSwitcher::new(Box::new(FakeSource(focus.clone())));
+
+        switcher.poll(&config).unwrap();
+        assert_eq!(config.read().unwrap().active_profile, "Default");
+
+        *focus.lock().unwrap() = window("Krita");
+        switcher.poll(&config).unwrap();
+        assert_eq!(config.read().unwrap().active_profile, "Krita");
+
+        *focus.lock().unwrap() = window("firefox");
+        switcher.poll(&config).unwrap();
+        assert_eq!(config.read().unwrap().active_profile, "Default");
+    }
+
+    #[test]
+    fn reapplies_rule_after_config_is_replaced() {
+        let config = RwLock::new(config_with_krita_rule());
+        let focus = Arc::new(Mutex::new(window("krita")));
+        let mut switcher = ProfileSwitcher::new(Box::new(FakeSource(focus.clone())));
+
+        switcher.poll(&config).unwrap();
+        assert_eq!(config.read().unwrap().active_profile, "Krita");
+
+        // A reload brings back the file's active profile while Krita keeps focus.
+        *config.write().unwrap() = config_with_krita_rule();
+        switcher.config_replaced();
+        switcher.poll(&config).unwrap();
+        assert_eq!(config.read().unwrap().active_profile, "Krita");
+
+        *focus.lock().unwrap() = window("firefox");
+        switcher.poll(&config).unwrap();
+        assert_eq!(config.read().unwrap().active_profile, "Default");
+    }
+}
@@ -1,13 +1,42 @@
+mod area;
+mod buttons;
+mod calibration;
+mod pressure_curve;
+mod status;
+mod test_panel;
+
+use std::time::{Duration, Instant};
+
 use eframe::egui;
-use crate::config::AppConfig;
+use crate::config::{
+    AppConfig, FocusDetector, PenMode, Profile, ProfileRule, TABLET_BUTTON_IDS,
+};
+use crate::control::{ClientRequest, ControlClient, DriverEvent};
+
+/// How often to retry connecting to a driver that isn't running.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+const DRIVER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Driver messages kept for display.
+const MAX_DRIVER_MESSAGES: usize = 5;
 
 pub fn run_gui() -> Result<(), eframe::Error> {
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default().with_inner_size([400.0, 400.0]),
+        viewport: egui::ViewportBuilder::default().with_inner_size([560.0, 600.0]),
         ..Default::default()
     };
-    let app = ConfigEditor::new(AppConfig::load());
-    
+    let first_run = !AppConfig::get_config_path().exists();
+    let mut app = match AppConfig::try_load() {
+        Ok(config) => ConfigEditor::new(config),
+        Err(e) => {
+            let mut app = ConfigEditor::new(AppConfig::default());
+            app.status_msg = format!("Error loading settings, showing defaults: {}", e);
+            app
+        }
+    };
+    if first_run {
+        app.tab = Tab::Calibrate;
+    }
+
     eframe::run_native(
         "Tablet Settings",
         options,
@@ -15,57 +44,451 @@ pub fn run_gui() -> Result<(), eframe::Error> {
     )
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum Tab {
+    General,
+    Pressure,
+    Area,
+    Buttons,
+    Test,
+    Status,
+    Calibrate,
+}
+
+impl Tab {
+    const ALL: [Tab; 7] = [
+        Tab::General,
+        Tab::Pressure,
+        Tab::Area,
+        Tab::Buttons,
+        Tab::Test,
+        Tab::Status,
+        Tab::Calibrate,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Tab::General => "General",
+            Tab::Pressure => "Pressure",
+            Tab::Area => "Area",
+            Tab::Buttons => "Buttons",
+            Tab::Test => "Test",
+            Tab::Status => "Status",
+            Tab::Calibrate => "Calibrate",
+        }
+    }
+}
+
 struct ConfigEditor {
     config: AppConfig,
+    tab: Tab,
+    recording: Option<buttons::ButtonRef>,
+    /// Index of the pressure curve point being dragged.
+    curve_drag: Option<usize>,
+    area_drag: Option<area::AreaDrag>,
+    /// Keeps the tablet area's shape matching the screen region while resizing.
+    lock_aspect: bool,
+    monitors: Vec<crate::monitors::Monitor>,
+    driver: Option<ControlClient>,
+    last_connect_attempt: Option<Instant>,
+    driver_messages: Vec<String>,
+    canvas: test_panel::TestCanvas,
+    diagnostics: status::Diagnostics,
+    calibrator: Option<crate::calibrate::Calibrator>,
+    profile_name: String,
     status_msg: String,
+    /// Send every change to the driver as it is made.
+    live_preview: bool,
+    /// The config as last saved or loaded, serialized to spot unsaved changes.
+    saved: String,
+    /// The config the driver was last told to preview, `None` while it uses the saved one.
+    sent: Option<String>,
 }
 
 impl ConfigEditor {
     fn new(config: AppConfig) -> Self {
         Self {
-            config,
+            profile_name: config.active().name.clone(),
+            tab: Tab::General,
+            recording: None,
+            curve_drag: None,
+            area_drag: None,
+            lock_aspect: true,
+            monitors: area::detect_monitors(),
+            driver: None,
+            last_connect_attempt: None,
+            driver_messages: Vec::new(),
+            canvas: Default::default(),
+            diagnostics: Default::default(),
+            calibrator: None,
             status_msg: String::new(),
+            live_preview: false,
+            saved: snapshot(&config),
+            sent: None,
+            config,
+        }
+    }
+
+    fn select_profile(&mut self, name: String) {
+        self.profile_name = name.clone();
+        self.config.active_profile = name;
+    }
+
+    fn unique_profile_name(&self, base: &str) -> String {
+        let mut name = base.to_string();
+        let mut n = 2;
+        while self.config.profiles.iter().any(|p| p.name == name) {
+            name = format!("{} {}", base, n);
+            n += 1;
+        }
+        name
+    }
+
+    fn profiles_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Profile:");
+            let mut selected = self.config.active().name.clone();
+            egui::ComboBox::from_id_salt("active_profile")
+                .selected_text(&selected)
+                .show_ui(ui, |ui| {
+                    for profile in &self.config.profiles {
+                        ui.selectable_value(&mut selected, profile.name.clone(), &profile.name);
+                    }
+                });
+            if selected != self.config.active_profile {
+                self.select_profile(selected);
+            }
+            let driver_profile = self.diagnostics.driver_status.as_ref().map(|status| &status.active_profile);
+            if self.driver.is_some()
+                && let Some(driver_profile) = driver_profile
+                && driver_profile != &self.config.active().name
+            {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("The driver is currently using \"{}\"", driver_profile),
+                );
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("New").clicked() {
+                let name = self.unique_profile_name("Profile");
+                self.config.profiles.push(Profile::new(&name));
+                self.select_profile(name);
+            }
+            if ui.button("Duplicate").clicked() {
+                let mut copy = self.config.active().clone();
+                copy.name = self.unique_profile_name(&copy.name);
+                let name = copy.name.clone();
+                self.config.profiles.push(copy);
+                self.select_profile(name);
+            }
+            let can_delete = self.config.profiles.len() > 1;
+            if ui.add_enabled(can_delete, egui::Button::new("Delete")).clicked() {
+                self.config.profiles.remove(self.config.active_index());
+                let name = self.config.profiles[0].name.clone();
+                self.select_profile(name);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.profile_name);
+            let name = self.profile_name.trim().to_string();
+            let taken = self.config.profiles.iter().any(|p| p.name == name);
+            if ui.add_enabled(!name.is_empty() && !taken, egui::Button::new("Rename")).clicked() {
+                let old = self.config.active().name.clone();
+                self.config.rename_profile(&old, &name);
+                self.select_profile(name);
+            }
+        });
+    }
+
+    fn switching_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Cycle profiles with tablet button:");
+            let selected_text = match self.config.profile_cycle_button {
+                Some(id) => id.to_string(),
+                None => "None".to_string(),
+            };
+            egui::ComboBox::from_id_salt("profile_cycle_button")
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.config.profile_cycle_button, None, "None");
+                    for id in TABLET_BUTTON_IDS {
+                        ui.selectable_value(
+                            &mut self.config.profile_cycle_button,
+                            Some(id),
+                            id.to_string(),
+                        );
+                    }
+                });
+        });
+
+        ui.collapsing("Application rules", |ui| self.rules_ui(ui));
+    }
+
+    fn rules_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Focus detector:");
+            egui::ComboBox::from_id_salt("focus_detector")
+                .selected_text(format!("{:?}", self.config.focus_detector))
+                .show_ui(ui, |ui| {
+                    for detector in [
+                        FocusDetector::Auto,
+                        FocusDetector::Disabled,
+                        FocusDetector::X11,
+                        FocusDetector::SwayIpc,
+                        FocusDetector::Gnome,
+                        FocusDetector::Kde,
+                    ] {
+                        ui.selectable_value(
+                            &mut self.config.focus_detector,
+                            detector,
+                            format!("{:?}", detector),
+                        );
+                    }
+                });
+        });
+
+        let profile_names: Vec<String> =
+            self.config.profiles.iter().map(|p| p.name.clone()).collect();
+        let mut removed = None;
+
+        egui::Grid::new("profile_rules").striped(true).show(ui, |ui| {
+            ui.label("Window class");
+            ui.label("Process");
+            ui.label("Profile");
+            ui.end_row();
+
+            for (i, rule) in self.config.profile_rules.iter_mut().enumerate() {
+                optional_text_edit(ui, &mut rule.window_class);
+                optional_text_edit(ui, &mut rule.process_name);
+                egui::ComboBox::from_id_salt(("rule_profile", i))
+                    .selected_text(&rule.profile)
+                    .show_ui(ui, |ui| {
+                        for name in &profile_names {
+                            ui.selectable_value(&mut rule.profile, name.clone(), name);
+                        }
+                    });
+                if ui.button("Remove").clicked() {
+                    removed = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+
+        if let Some(i) = removed {
+            self.config.profile_rules.remove(i);
+        }
+        if ui.button("Add rule").clicked() {
+            self.config.profile_rules.push(ProfileRule {
+                profile: self.config.active_profile.clone(),
+                ..Default::default()
+            });
         }
     }
 }
 
-impl eframe::App for ConfigEditor {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {      
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.label("Pressure Threshold:");
-            ui.add(egui::Slider::new(
-                &mut self.config.pressure_threshold, 
-                0..=2048)
-                .text("levels")
-                .step_by(10.0)
-            );
-
-            ui.add_space(10.0);
-
-            ui.label("Sensitivity:");
-            ui.add(egui::Slider::new(
-                &mut self.config.sensitivity, 
-                0.1..=10.0)
-                .text("x")
-                .step_by(0.1)
-            );
+fn snapshot(config: &AppConfig) -> String {
+    serde_json::to_string(config).unwrap_or_default()
+}
 
-            ui.separator();
+fn optional_text_edit(ui: &mut egui::Ui, value: &mut Option<String>) {
+    let mut text = value.clone().unwrap_or_default();
+    ui.add(egui::TextEdit::singleline(&mut text).desired_width(100.0));
+    *value = Some(text).filter(|t| !t.trim().is_empty());
+}
+
+impl ConfigEditor {
+    fn general_ui(&mut self, ui: &mut egui::Ui) {
+        let profile = self.config.active_mut();
+
+        ui.label("Smoothing:");
+        ui.add(egui::Slider::new(
+            &mut profile.smoothing,
+            0.0..=1.0)
+            .step_by(0.05)
+        );
+
+        ui.label("Dead zone for a still pen (tablet units, 0 = off):");
+        ui.add(egui::Slider::new(&mut profile.dead_zone, 0..=40));
+
+        ui.label("Pen:");
+        ui.radio_value(&mut profile.pen_mode, PenMode::Normal, "Hover moves, tip clicks");
+        ui.radio_value(
+            &mut profile.pen_mode,
+            PenMode::TipClickDisabled,
+            "Tip never clicks, use a button (presentations)",
+        );
+        ui.radio_value(&mut profile.pen_mode, PenMode::TouchOnly, "Cursor moves only while touching");
+
+        ui.separator();
+
+        self.switching_ui(ui);
+    }
+
+    /// Connects to the running driver if needed and handles what it sent.
+    fn poll_driver(&mut self) {
+        let retry = self
+            .last_connect_attempt
+            .is_none_or(|attempt| attempt.elapsed() > RECONNECT_INTERVAL);
+        if self.driver.is_none() && retry {
+            self.last_connect_attempt = Some(Instant::now());
+            self.driver = ControlClient::connect().ok();
+            // A newly started driver runs with the saved settings.
+            self.sent = None;
+        }
+
+        let Some(driver) = &self.driver else {
+            return;
+        };
+        let events = match driver.poll() {
+            Ok(events) => events,
+            Err(_) => {
+                self.driver = None;
+                return;
+            }
+        };
+        for event in events {
+            match event {
+                DriverEvent::ConfigIssue { message, .. } => {
+                    self.driver_messages.push(message);
+                    if self.driver_messages.len() > MAX_DRIVER_MESSAGES {
+                        self.driver_messages.remove(0);
+                    }
+                }
+                DriverEvent::PenSample(sample) => {
+                    if let Some(calibrator) = &mut self.calibrator {
+                        calibrator.record(&sample);
+                    }
+                    self.canvas.record(sample);
+                }
+                DriverEvent::Status(status) => self.diagnostics.driver_status = Some(status),
+            }
+        }
+    }
+
+    /// Makes the driver use the current settings without saving them.
+    fn apply(&mut self, current: String) {
+        let Some(driver) = &self.driver else {
+            return;
+        };
+        match driver.send(&ClientRequest::Preview(Box::new(self.config.clone()))) {
+            Ok(()) => self.sent = Some(current),
+            Err(e) => {
+                self.status_msg = format!("Error sending settings to the driver: {}", e);
+                self.driver = None;
+            }
+        }
+    }
+
+    /// Drops unsaved changes here and in the driver.
+    fn revert(&mut self) {
+        match AppConfig::try_load() {
+            Ok(config) => {
+                self.profile_name = config.active().name.clone();
+                self.saved = snapshot(&config);
+                self.config = config;
+                self.status_msg = "Reverted to the saved settings.".to_string();
+            }
+            Err(e) => self.status_msg = format!("Error loading settings: {}", e),
+        }
+        if self.sent.take().is_some()
+            && let Some(driver) = &self.driver
+        {
+            let _ = driver.send(&ClientRequest::Revert);
+        }
+    }
 
-            if ui.button("Save").clicked() {
+    fn save_ui(&mut self, ui: &mut egui::Ui) {
+        let validation = self.config.validate();
+        for error in &validation.errors {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        for warning in &validation.warnings {
+            ui.colored_label(ui.visuals().warn_fg_color, warning);
+        }
+        for message in &self.driver_messages {
+            ui.colored_label(ui.visuals().warn_fg_color, format!("Driver: {}", message));
+        }
+
+        let current = snapshot(&self.config);
+        let unsaved = current != self.saved;
+        if self.live_preview && validation.is_valid()
+            && current != *self.sent.as_ref().unwrap_or(&self.saved)
+        {
+            self.apply(current.clone());
+        }
+
+        ui.horizontal(|ui| {
+            let connected = self.driver.is_some();
+            ui.add_enabled(connected, egui::Checkbox::new(&mut self.live_preview, "Live preview"))
+                .on_hover_text("Send every change to the running driver without saving it");
+            let can_apply = connected && validation.is_valid() && !self.live_preview;
+            if ui.add_enabled(can_apply, egui::Button::new("Apply")).clicked() {
+                self.apply(current.clone());
+                if self.sent.is_some() {
+                    self.status_msg = "Applied to the driver, not saved.".to_string();
+                }
+            }
+            if ui.add_enabled(unsaved || self.sent.is_some(), egui::Button::new("Revert")).clicked() {
+                self.revert();
+            }
+            if ui.add_enabled(validation.is_valid(), egui::Button::new("Save")).clicked() {
                 match self.config.save() {
-                    Ok(_) => self.status_msg = "Saved!".to_string(),
+                    Ok(_) => {
+                        // The driver picks the file up itself.
+                        self.saved = current;
+                        self.sent = None;
+                        self.status_msg = "Saved!".to_string();
+                    }
                     Err(e) => self.status_msg = format!("Error: {}", e),
                 }
             }
-            
-            if !self.status_msg.is_empty() {
-                ui.label(&self.status_msg);
+            if unsaved {
+                ui.colored_label(ui.visuals().warn_fg_color, "Unsaved changes");
             }
         });
+
+        if !self.status_msg.is_empty() {
+            ui.label(&self.status_msg);
+        }
     }
-    
-    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
-        let _ = self.config.save();
+}
+
+impl eframe::App for ConfigEditor {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.capture_key_combo(ctx);
+        self.poll_driver();
+        // Keep draining driver events even while nothing on screen changes.
+        ctx.request_repaint_after(if self.driver.is_some() {
+            DRIVER_POLL_INTERVAL
+        } else {
+            RECONNECT_INTERVAL
+        });
+
+        egui::TopBottomPanel::top("profile").show(ctx, |ui| {
+            self.profiles_ui(ui);
+            ui.separator();
+            ui.horizontal(|ui| {
+                for tab in Tab::ALL {
+                    ui.selectable_value(&mut self.tab, tab, tab.label());
+                }
+            });
+        });
+
+        egui::TopBottomPanel::bottom("save").show(ctx, |ui| self.save_ui(ui));
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| match self.tab {
+                Tab::General => self.general_ui(ui),
+                Tab::Pressure => self.pressure_ui(ui),
+                Tab::Area => self.area_ui(ui),
+                Tab::Buttons => self.buttons_ui(ui),
+                Tab::Test => self.test_ui(ui),
+                Tab::Status => self.status_ui(ui),
+                Tab::Calibrate => self.calibration_ui(ui),
+            });
+        });
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,310 @@
+use eframe::egui;
+
+use super::ConfigEditor;
+use crate::config::{
+    OutputArea, TabletArea, TABLET_HEIGHT_MM, TABLET_MAX_X, TABLET_MAX_Y, TABLET_WIDTH_MM,
+};
+use crate::monitors::{self, Monitor};
+
+const PREVIEW_WIDTH: f32 = 320.0;
+const DESKTOP_PREVIEW_HEIGHT: f32 = 180.0;
+/// How close, in screen points, the pointer must be to grab a corner of the area.
+const GRAB_RADIUS: f32 = 10.0;
+/// Desktop assumed when the monitor layout can't be read.
+const FALLBACK_DESKTOP: (i32, i32, i32, i32) = (0, 0, 1920, 1080);
+
+/// What the pointer is doing to the tablet area.
+#[derive(Clone, Copy, PartialEq)]
+pub enum AreaDrag {
+    Move,
+    /// Index into `corners`: top-left, top-right, bottom-right, bottom-left.
+    Corner(usize),
+}
+
+fn corners(area: &TabletArea) -> [[i32; 2]; 4] {
+    [
+        [area.x_min, area.y_min],
+        [area.x_max, area.y_min],
+        [area.x_max, area.y_max],
+        [area.x_min, area.y_max],
+    ]
+}
+
+fn moves_x_min(corner: usize) -> bool {
+    corner == 0 || corner == 3
+}
+
+fn moves_y_min(corner: usize) -> bool {
+    corner == 0 || corner == 1
+}
+
+/// Width / height in raw units of a tablet area that has the same physical
+/// shape as a region of `width` x `height` pixels.
+fn raw_ratio(width: f32, height: f32) -> f32 {
+    let mm_per_x = TABLET_WIDTH_MM / TABLET_MAX_X as f32;
+    let mm_per_y = TABLET_HEIGHT_MM / TABLET_MAX_Y as f32;
+    width / height * mm_per_y / mm_per_x
+}
+
+/// Adjusts the edges moved by `corner` so that the area has `ratio`, keeping
+/// the opposite corner in place.
+fn fit_ratio(area: &mut TabletArea, corner: usize, ratio: f32) {
+    let max_width = if moves_x_min(corner) { area.x_max } else { TABLET_MAX_X - area.x_min };
+    let max_height = if moves_y_min(corner) { area.y_max } else { TABLET_MAX_Y - area.y_min };
+    let width = ((area.x_max - area.x_min) as f32).min(max_height as f32 * ratio).min(max_width as f32);
+    let height = width / ratio;
+
+    if moves_x_min(corner) {
+        area.x_min = area.x_max - width.round() as i32;
+    } else {
+        area.x_max = area.x_min + width.round() as i32;
+    }
+    if moves_y_min(corner) {
+        area.y_min = area.y_max - height.round() as i32;
+    } else {
+        area.y_max = area.y_min + height.round() as i32;
+    }
+}
+
+/// The largest area with `ratio`, centered horizontally and starting at the top edge.
+fn largest_area(ratio: f32) -> TabletArea {
+    let width = (TABLET_MAX_Y as f32 * ratio).min(TABLET_MAX_X as f32).round() as i32;
+    let height = (width as f32 / ratio).round() as i32;
+    let x_min = (TABLET_MAX_X - width) / 2;
+    TabletArea {
+        x_min,
+        y_min: 0,
+        x_max: x_min + width,
+        y_max: height,
+    }
+}
+
+pub fn detect_monitors() -> Vec<Monitor> {
+    monitors::detect().unwrap_or_else(|e| {
+        eprintln!("Could not read the monitor layout: {}", e);
+        Vec::new()
+    })
+}
+
+impl ConfigEditor {
+    /// Desktop bounding box in pixels, from the detected monitors or a fallback.
+    fn desktop(&self) -> (i32, i32, i32, i32) {
+        monitors::desktop_bounds(&self.monitors).unwrap_or(FALLBACK_DESKTOP)
+    }
+
+    fn output_ratio(&self) -> f32 {
+        let (_, _, width, height) = self.desktop();
+        let output = self.config.active().output;
+        raw_ratio(output.width * width as f32, output.height * height as f32)
+    }
+
+    pub(super) fn area_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Tablet area");
+        let ratio = self.output_ratio();
+        ui.horizontal(|ui| {
+            let profile = self.config.active_mut();
+            if ui.button("Full").clicked() {
+                profile.area = TabletArea::default();
+            }
+            if ui.button("Match screen ratio").clicked() {
+                profile.area = largest_area(ratio);
+            }
+            ui.checkbox(&mut profile.left_handed, "Left-hand")
+                .on_hover_text("The tablet is turned around, with the buttons on the right");
+            ui.checkbox(&mut self.lock_aspect, "Lock aspect ratio");
+        });
+        ui.label("Drag the area to move it, or its corners to resize it.");
+        self.tablet_area_editor(ui, ratio);
+
+        let area = &mut self.config.active_mut().area;
+        ui.horizontal(|ui| {
+            ui.label("X:");
+            ui.add(egui::DragValue::new(&mut area.x_min).range(0..=area.x_max - 1));
+            ui.add(egui::DragValue::new(&mut area.x_max).range(area.x_min + 1..=TABLET_MAX_X));
+            ui.label("Y:");
+            ui.add(egui::DragValue::new(&mut area.y_min).range(0..=area.y_max - 1));
+            ui.add(egui::DragValue::new(&mut area.y_max).range(area.y_min + 1..=TABLET_MAX_Y));
+        });
+
+        ui.separator();
+        ui.heading("Screen region");
+        if self.monitors.is_empty() {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "Monitor layout unknown, showing a single 1920x1080 screen.",
+            );
+        }
+        self.output_presets_ui(ui);
+        self.desktop_preview(ui);
+
+        let output = &mut self.config.active_mut().output;
+        ui.horizontal(|ui| {
+            for (label, value) in [
+                ("X:", &mut output.x),
+                ("Y:", &mut output.y),
+                ("Width:", &mut output.width),
+                ("Height:", &mut output.height),
+            ] {
+                ui.label(label);
+                ui.add(egui::DragValue::new(value).range(0.0..=1.0).speed(0.005).max_decimals(3));
+            }
+        });
+    }
+
+    fn tablet_area_editor(&mut self, ui: &mut egui::Ui, ratio: f32) {
+        let size = egui::vec2(PREVIEW_WIDTH, PREVIEW_WIDTH * TABLET_HEIGHT_MM / TABLET_WIDTH_MM);
+        let (response, painter) = ui.allocate_painter(
+            size + egui::Vec2::splat(2.0 * GRAB_RADIUS),
+            egui::Sense::drag(),
+        );
+        let rect = response.rect.shrink(GRAB_RADIUS);
+        let scale = egui::vec2(
+            rect.width() / TABLET_MAX_X as f32,
+            rect.height() / TABLET_MAX_Y as f32,
+        );
+        let to_screen = |[x, y]: [i32; 2]| {
+            rect.left_top() + egui::vec2(x as f32 * scale.x, y as f32 * scale.y)
+        };
+
+        let area = &mut self.config.active_mut().area;
+        let screen_area = egui::Rect::from_two_pos(
+            to_screen([area.x_min, area.y_min]),
+            to_screen([area.x_max, area.y_max]),
+        );
+        let hovered = response.hover_pos().and_then(|pos| {
+            let corner = corners(area)
+                .iter()
+                .position(|&corner| to_screen(corner).distance(pos) < GRAB_RADIUS);
+            match corner {
+                Some(i) => Some(AreaDrag::Corner(i)),
+                None if screen_area.contains(pos) => Some(AreaDrag::Move),
+                None => None,
+            }
+        });
+
+        if response.drag_started() {
+            self.area_drag = hovered;
+        }
+        if response.drag_stopped() {
+            self.area_drag = None;
+        }
+        match (self.area_drag, response.interact_pointer_pos()) {
+            (Some(AreaDrag::Move), _) => {
+                let delta = response.drag_delta();
+                let dx = (delta.x / scale.x).round() as i32;
+                let dy = (delta.y / scale.y).round() as i32;
+                let dx = dx.clamp(-area.x_min, TABLET_MAX_X - area.x_max);
+                let dy = dy.clamp(-area.y_min, TABLET_MAX_Y - area.y_max);
+                area.x_min += dx;
+                area.x_max += dx;
+                area.y_min += dy;
+                area.y_max += dy;
+            }
+            (Some(AreaDrag::Corner(i)), Some(pos)) => {
+                let x = (((pos.x - rect.left()) / scale.x).round() as i32).clamp(0, TABLET_MAX_X);
+                let y = (((pos.y - rect.top()) / scale.y).round() as i32).clamp(0, TABLET_MAX_Y);
+                // Corners may not cross, so the area never becomes empty.
+                if moves_x_min(i) {
+                    area.x_min = x.min(area.x_max - 1);
+                } else {
+                    area.x_max = x.max(area.x_min + 1);
+                }
+                if moves_y_min(i) {
+                    area.y_min = y.min(area.y_max - 1);
+                } else {
+                    area.y_max = y.max(area.y_min + 1);
+                }
+                if self.lock_aspect {
+                    fit_ratio(area, i, ratio);
+                }
+            }
+            _ => {}
+        }
+
+        let visuals = ui.visuals();
+        painter.rect_filled(rect, 4.0, visuals.extreme_bg_color);
+        painter.rect_stroke(rect, 4.0, egui::Stroke::new(1.0, visuals.weak_text_color()));
+        let screen_area = egui::Rect::from_two_pos(
+            to_screen([area.x_min, area.y_min]),
+            to_screen([area.x_max, area.y_max]),
+        );
+        painter.rect_filled(screen_area, 0.0, visuals.selection.bg_fill.gamma_multiply(0.4));
+        painter.rect_stroke(screen_area, 0.0, egui::Stroke::new(2.0, visuals.selection.bg_fill));
+        for (i, corner) in corners(area).into_iter().enumerate() {
+            let active = self.area_drag == Some(AreaDrag::Corner(i))
+                || hovered == Some(AreaDrag::Corner(i));
+            painter.circle_filled(
+                to_screen(corner),
+                if active { 6.0 } else { 4.0 },
+                visuals.selection.bg_fill,
+            );
+        }
+    }
+
+    fn output_presets_ui(&mut self, ui: &mut egui::Ui) {
+        let (desktop_x, desktop_y, width, height) = self.desktop();
+        let output = &mut self.config.active_mut().output;
+        ui.horizontal_wrapped(|ui| {
+            if ui.button("All screens").clicked() {
+                *output = OutputArea::default();
+            }
+            for monitor in &self.monitors {
+                if ui.button(&monitor.name).clicked() {
+                    *output = OutputArea {
+                        x: (monitor.x - desktop_x) as f32 / width as f32,
+                        y: (monitor.y - desktop_y) as f32 / height as f32,
+                        width: monitor.width as f32 / width as f32,
+                        height: monitor.height as f32 / height as f32,
+                    };
+                }
+            }
+        });
+    }
+
+    fn desktop_preview(&mut self, ui: &mut egui::Ui) {
+        let (desktop_x, desktop_y, width, height) = self.desktop();
+        let scale = (PREVIEW_WIDTH / width as f32).min(DESKTOP_PREVIEW_HEIGHT / height as f32);
+        let (response, painter) = ui.allocate_painter(
+            egui::vec2(width as f32 * scale, height as f32 * scale),
+            egui::Sense::hover(),
+        );
+        let rect = response.rect;
+        let visuals = ui.visuals();
+
+        let monitors = if self.monitors.is_empty() {
+            vec![Monitor {
+                name: String::new(),
+                x: desktop_x,
+                y: desktop_y,
+                width,
+                height,
+            }]
+        } else {
+            self.monitors.clone()
+        };
+        for monitor in &monitors {
+            let monitor_rect = egui::Rect::from_min_size(
+                rect.left_top()
+                    + egui::vec2((monitor.x - desktop_x) as f32, (monitor.y - desktop_y) as f32) * scale,
+                egui::vec2(monitor.width as f32, monitor.height as f32) * scale,
+            );
+            painter.rect_filled(monitor_rect.shrink(1.0), 2.0, visuals.extreme_bg_color);
+            painter.rect_stroke(monitor_rect.shrink(1.0), 2.0, egui::Stroke::new(1.0, visuals.weak_text_color()));
+            painter.text(
+                monitor_rect.center(),
+                egui::Align2::CENTER_CENTER,
+                &monitor.name,
+                egui::FontId::proportional(12.0),
+                visuals.text_color(),
+            );
+        }
+
+        let output = self.config.active().output;
+        let output_rect = egui::Rect::from_min_size(
+            rect.left_top() + egui::vec2(output.x * rect.width(), output.y * rect.height()),
+            egui::vec2(output.width * rect.width(), output.height * rect.height()),
+        );
+        painter.rect_filled(output_rect, 0.0, visuals.selection.bg_fill.gamma_multiply(0.4));
+        painter.rect_stroke(output_rect, 0.0, egui::Stroke::new(2.0, visuals.selection.bg_fill));
+    }
+}
@@ -0,0 +1,459 @@
+use std::str::FromStr;
+
+use eframe::egui;
+use evdev::Key;
+
+use super::ConfigEditor;
+use crate::config::{
+    Action, ButtonMap, DragMode, MediaStripMode, Mode, MouseButton, Profile, RepeatMode, RepeatedKeys,
+    ScrollDirection, SequenceStep, SliderAction, PEN_BUTTON_IDS, TABLET_BUTTON_IDS,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ButtonGroup {
+    Tablet,
+    TabletHold,
+    TabletDoubleTap,
+    Pen,
+    Media,
+    ShiftedTablet,
+    ShiftedMedia,
+}
+
+impl ButtonGroup {
+    fn map(self, profile: &mut Profile) -> &mut ButtonMap {
+        match self {
+            ButtonGroup::Tablet => &mut profile.tablet_buttons,
+            ButtonGroup::TabletHold => &mut profile.tablet_hold,
+            ButtonGroup::TabletDoubleTap => &mut profile.tablet_double_tap,
+            ButtonGroup::Pen => &mut profile.pen_buttons,
+            ButtonGroup::Media => &mut profile.media_buttons,
+            ButtonGroup::ShiftedTablet => &mut profile.shifted_tablet_buttons,
+            ButtonGroup::ShiftedMedia => &mut profile.shifted_media_buttons,
+        }
+    }
+}
+
+/// A button of the active profile, e.g. the one waiting for a recorded key combo.
+#[derive(Clone, Copy, PartialEq)]
+pub struct ButtonRef {
+    pub group: ButtonGroup,
+    pub id: u8,
+    /// Step of a key sequence, if the combo is recorded for one.
+    pub step: Option<usize>,
+}
+
+const MODIFIERS: [(&str, &str); 4] = [
+    ("Ctrl", "KEY_LEFTCTRL"),
+    ("Shift", "KEY_LEFTSHIFT"),
+    ("Alt", "KEY_LEFTALT"),
+    ("Super", "KEY_LEFTMETA"),
+];
+
+/// Name of the evdev key at the same position as `key` on a US layout.
+fn evdev_key_name(key: egui::Key) -> Option<String> {
+    use egui::Key as K;
+    let name = match key {
+        K::ArrowDown => "KEY_DOWN",
+        K::ArrowLeft => "KEY_LEFT",
+        K::ArrowRight => "KEY_RIGHT",
+        K::ArrowUp => "KEY_UP",
+        K::Escape => "KEY_ESC",
+        K::Tab => "KEY_TAB",
+        K::Backspace => "KEY_BACKSPACE",
+        K::Enter => "KEY_ENTER",
+        K::Space => "KEY_SPACE",
+        K::Insert => "KEY_INSERT",
+        K::Delete => "KEY_DELETE",
+        K::Home => "KEY_HOME",
+        K::End => "KEY_END",
+        K::PageUp => "KEY_PAGEUP",
+        K::PageDown => "KEY_PAGEDOWN",
+        K::Copy => "KEY_COPY",
+        K::Cut => "KEY_CUT",
+        K::Paste => "KEY_PASTE",
+        K::Colon | K::Semicolon => "KEY_SEMICOLON",
+        K::Comma => "KEY_COMMA",
+        K::Backslash | K::Pipe => "KEY_BACKSLASH",
+        K::Slash | K::Questionmark => "KEY_SLASH",
+        K::OpenBracket => "KEY_LEFTBRACE",
+        K::CloseBracket => "KEY_RIGHTBRACE",
+        K::Backtick => "KEY_GRAVE",
+        K::Minus => "KEY_MINUS",
+        K::Period => "KEY_DOT",
+        K::Plus | K::Equals => "KEY_EQUAL",
+        K::Quote => "KEY_APOSTROPHE",
+        // Letters, digits and F-keys share their names with evdev.
+        other => return Some(format!("KEY_{}", other.name())).filter(|n| Key::from_str(n).is_ok()),
+    };
+    Some(name.to_string())
+}
+
+/// Turns a key press into the config's list of key names, modifiers first.
+fn key_combo(event: &egui::Event) -> Option<Vec<String>> {
+    let (key, modifiers) = match event {
+        egui::Event::Key { key, physical_key, pressed: true, modifiers, .. } => {
+            (physical_key.unwrap_or(*key), *modifiers)
+        }
+        // The clipboard shortcuts reach egui as dedicated events instead of key presses.
+        egui::Event::Copy => (egui::Key::C, egui::Modifiers::CTRL),
+        egui::Event::Cut => (egui::Key::X, egui::Modifiers::CTRL),
+        egui::Event::Paste(_) => (egui::Key::V, egui::Modifiers::CTRL),
+        _ => return None,
+    };
+
+    let pressed = [modifiers.ctrl, modifiers.shift, modifiers.alt, modifiers.mac_cmd];
+    let mut keys: Vec<String> = MODIFIERS
+        .iter()
+        .zip(pressed)
+        .filter(|(_, pressed)| *pressed)
+        .map(|((_, name), _)| name.to_string())
+        .collect();
+    keys.push(evdev_key_name(key)?);
+    Some(keys)
+}
+
+/// Action kinds offered for a button, each with the action it starts out as.
+fn action_kinds() -> [(&'static str, Action); 11] {
+    [
+        ("None", Action::None),
+        ("Keys", Action::Keys(Vec::new())),
+        ("Repeating keys", Action::RepeatKeys(RepeatedKeys::default())),
+        ("Key sequence", Action::Sequence(vec![SequenceStep::default()])),
+        ("Mouse button", Action::Mouse(MouseButton::Left)),
+        ("Scroll", Action::Scroll(ScrollDirection::Up)),
+        ("Type text", Action::Text(String::new())),
+        ("Run command", Action::Command(String::new())),
+        ("Switch profile", Action::SwitchProfile(String::new())),
+        ("Toggle mode", Action::ToggleMode(Mode::MediaSlider)),
+        ("Pen drag", Action::Drag(DragMode::Scroll)),
+    ]
+}
+
+fn enum_combo<T: PartialEq + Copy + std::fmt::Debug>(
+    ui: &mut egui::Ui,
+    id: impl std::hash::Hash,
+    value: &mut T,
+    options: &[T],
+) {
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(format!("{:?}", value))
+        .show_ui(ui, |ui| {
+            for &option in options {
+                ui.selectable_value(value, option, format!("{:?}", option));
+            }
+        });
+}
+
+/// The + menu that adds or removes modifiers the recorder can't capture, like Super.
+fn modifiers_menu(ui: &mut egui::Ui, keys: &mut Vec<String>) {
+    ui.menu_button("+", |ui| {
+        for (label, name) in MODIFIERS {
+            let mut held = keys.iter().any(|key| key == name);
+            if ui.checkbox(&mut held, label).changed() {
+                if held {
+                    keys.insert(0, name.to_string());
+                } else {
+                    keys.retain(|key| key != name);
+                }
+            }
+        }
+    });
+}
+
+fn combo_label(keys: &[String]) -> String {
+    if keys.is_empty() {
+        return "—".to_string();
+    }
+    keys.iter()
+        .map(|key| key.strip_prefix("KEY_").unwrap_or(key))
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+impl ConfigEditor {
+    /// While a button is being recorded, the next key press becomes its mapping.
+    /// Key events are swallowed so Tab or Enter don't act on the window.
+    pub(super) fn capture_key_combo(&mut self, ctx: &egui::Context) {
+        let Some(target) = self.recording else {
+            return;
+        };
+        let combo = ctx.input_mut(|input| {
+            let combo = input.events.iter().find_map(key_combo);
+            input.events.retain(|event| {
+                !matches!(event, egui::Event::Key { .. } | egui::Event::Copy
+                    | egui::Event::Cut | egui::Event::Paste(_) | egui::Event::Text(_))
+            });
+            combo
+        });
+        if let Some(keys) = combo {
+            let map = target.group.map(self.config.active_mut());
+            match (target.step, map.get_mut(&target.id)) {
+                (Some(i), Some(Action::Sequence(steps))) if i < steps.len() => steps[i].keys = keys,
+                (None, Some(Action::RepeatKeys(repeated))) => repeated.keys = keys,
+                _ => {
+                    map.insert(target.id, Action::Keys(keys));
+                }
+            }
+            self.recording = None;
+        }
+    }
+
+    pub(super) fn buttons_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("Pick what each button does. For keys, click Record and press a combination; Super can only be added from the + menu.");
+
+        self.media_mode_ui(ui);
+        self.layer_shift_ui(ui);
+        ui.collapsing("Key repeat", |ui| self.key_repeat_ui(ui));
+
+        let media_ids: Vec<u8> = match self.config.active().media_mode {
+            MediaStripMode::Buttons => (0..self.config.calibration.media_zones.len() as u8).collect(),
+            MediaStripMode::Slider => Vec::new(),
+        };
+        let shift_button = self.config.layer_shift_button;
+        let shifted_tablet_ids: Vec<u8> = match shift_button {
+            Some(shift) => TABLET_BUTTON_IDS.into_iter().filter(|&id| id != shift).collect(),
+            None => Vec::new(),
+        };
+        let shifted_media_ids: &[u8] = if shift_button.is_some() { &media_ids } else { &[] };
+        let groups: [(ButtonGroup, &str, &[u8]); 7] = [
+            (ButtonGroup::Tablet, "Tablet buttons", &TABLET_BUTTON_IDS),
+            (ButtonGroup::TabletHold, "Tablet buttons, hold", &TABLET_BUTTON_IDS),
+            (ButtonGroup::TabletDoubleTap, "Tablet buttons, double-tap", &TABLET_BUTTON_IDS),
+            (ButtonGroup::Pen, "Pen buttons", &PEN_BUTTON_IDS),
+            (ButtonGroup::Media, "Media strip zones", &media_ids),
+            (ButtonGroup::ShiftedTablet, "Tablet buttons, shifted", &shifted_tablet_ids),
+            (ButtonGroup::ShiftedMedia, "Media strip zones, shifted", shifted_media_ids),
+        ];
+
+        for (group, title, ids) in groups.into_iter().filter(|(_, _, ids)| !ids.is_empty()) {
+            let gesture = matches!(group, ButtonGroup::TabletHold | ButtonGroup::TabletDoubleTap);
+            egui::CollapsingHeader::new(title).default_open(!gesture).show(ui, |ui| {
+                match group {
+                    ButtonGroup::TabletHold => self.button_timing_ui(ui),
+                    ButtonGroup::Pen => self.drag_ui(ui),
+                    _ => {}
+                }
+                egui::Grid::new(title).striped(true).show(ui, |ui| {
+                    for &id in ids {
+                        self.button_row(ui, ButtonRef { group, id, step: None });
+                        ui.end_row();
+                    }
+                });
+            });
+        }
+    }
+
+    fn drag_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("A Pen drag action scrolls, pans or zooms while its button is held and the pen moves.");
+        let drag = &mut self.config.active_mut().drag;
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut drag.step, 20..=500).text("tablet units per wheel notch"));
+            ui.checkbox(&mut drag.invert, "Invert scrolling");
+        });
+    }
+
+    fn key_repeat_ui(&mut self, ui: &mut egui::Ui) {
+        let repeat = &mut self.config.key_repeat;
+        ui.horizontal(|ui| {
+            ui.label("Held keys repeat:");
+            ui.radio_value(&mut repeat.mode, RepeatMode::System, "Like the keyboard");
+            ui.radio_value(&mut repeat.mode, RepeatMode::Driver, "With these settings");
+        });
+        ui.add_enabled_ui(repeat.mode == RepeatMode::Driver, |ui| {
+            ui.add(egui::Slider::new(&mut repeat.delay_ms, 100..=2000).text("ms before repeating"));
+            ui.add(egui::Slider::new(&mut repeat.interval_ms, 10..=500).text("ms between repeats"));
+        });
+        ui.checkbox(&mut repeat.repeat_modifiers, "Repeat Ctrl, Shift, Alt and Super too");
+        ui.label("Repeating keys actions use their own timing, whichever option is picked.");
+    }
+
+    fn layer_shift_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Layer shift button:");
+            let shift = &mut self.config.layer_shift_button;
+            egui::ComboBox::from_id_salt("layer_shift_button")
+                .selected_text(shift.map_or("None".to_string(), |id| id.to_string()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(shift, None, "None");
+                    for id in TABLET_BUTTON_IDS {
+                        ui.selectable_value(shift, Some(id), id.to_string());
+                    }
+                });
+        });
+        if self.config.layer_shift_button.is_some() {
+            ui.label("While it is held, the shifted actions below apply. Buttons without one keep their normal action.");
+        }
+    }
+
+    fn button_timing_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(
+            "A button with a hold or double-tap action sends its tap action when released, \
+             after waiting for a second tap if it has a double-tap action.",
+        );
+        let timing = &mut self.config.button_timing;
+        ui.add(egui::Slider::new(&mut timing.hold_ms, 100..=2000).text("ms until a press is a hold"));
+        ui.add(egui::Slider::new(&mut timing.double_tap_ms, 100..=1000).text("ms to wait for a double-tap"));
+    }
+
+    fn media_mode_ui(&mut self, ui: &mut egui::Ui) {
+        let profile = self.config.active_mut();
+        ui.horizontal(|ui| {
+            ui.label("Media strip:");
+            ui.radio_value(&mut profile.media_mode, MediaStripMode::Buttons, "Buttons");
+            ui.radio_value(&mut profile.media_mode, MediaStripMode::Slider, "Slider");
+        });
+        if profile.media_mode != MediaStripMode::Slider {
+            return;
+        }
+
+        let slider = &mut profile.media_slider;
+        ui.horizontal(|ui| {
+            ui.label("Dragging along the strip changes:");
+            egui::ComboBox::from_id_salt("media_slider_action")
+                .selected_text(format!("{:?}", slider.action))
+                .show_ui(ui, |ui| {
+                    for action in [
+                        SliderAction::Volume,
+                        SliderAction::Scroll,
+                        SliderAction::Zoom,
+                        SliderAction::BrushSize,
+                    ] {
+                        ui.selectable_value(&mut slider.action, action, format!("{:?}", action));
+                    }
+                });
+        });
+        ui.add(egui::Slider::new(&mut slider.step, 50..=1000).text("tablet units per step"));
+    }
+
+    /// Shows the recorded combo of `button`, or that it is being recorded.
+    fn record_ui(&mut self, ui: &mut egui::Ui, button: ButtonRef, keys: &[String]) {
+        if self.recording == Some(button) {
+            ui.colored_label(ui.visuals().warn_fg_color, "Press keys to record…");
+            if ui.button("Cancel").clicked() {
+                self.recording = None;
+            }
+        } else {
+            ui.monospace(combo_label(keys));
+            if ui.button("Record").clicked() {
+                self.recording = Some(button);
+            }
+        }
+    }
+
+    fn button_row(&mut self, ui: &mut egui::Ui, button: ButtonRef) {
+        let map = button.group.map(self.config.active_mut());
+        let original = map.get(&button.id).cloned().unwrap_or_default();
+        let mut action = original.clone();
+
+        ui.label(format!("{}", button.id));
+
+        let kinds = action_kinds();
+        let kind = kinds
+            .iter()
+            .find(|(_, kind)| std::mem::discriminant(kind) == std::mem::discriminant(&action))
+            .map_or("None", |(label, _)| label);
+        egui::ComboBox::from_id_salt(("action_kind", button.group as u8, button.id))
+            .selected_text(kind)
+            .show_ui(ui, |ui| {
+                for (label, default) in kinds {
+                    if ui.selectable_label(kind == label, label).clicked() && kind != label {
+                        action = default;
+                    }
+                }
+            });
+
+        let salt = (button.group as u8, button.id);
+        ui.horizontal(|ui| match &mut action {
+            Action::None => {}
+            Action::Keys(keys) => {
+                self.record_ui(ui, button, keys);
+                modifiers_menu(ui, keys);
+            }
+            Action::RepeatKeys(repeated) => {
+                self.record_ui(ui, button, &repeated.keys);
+                modifiers_menu(ui, &mut repeated.keys);
+                ui.add(egui::DragValue::new(&mut repeated.delay_ms).range(100..=2000).prefix("after ").suffix(" ms"));
+                ui.add(egui::DragValue::new(&mut repeated.interval_ms).range(10..=500).prefix("every ").suffix(" ms"));
+            }
+            Action::Sequence(steps) => {
+                ui.vertical(|ui| {
+                    let mut removed = None;
+                    for (i, step) in steps.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            self.record_ui(ui, ButtonRef { step: Some(i), ..button }, &step.keys);
+                            ui.add(egui::DragValue::new(&mut step.delay_ms).range(0..=5000).suffix(" ms"));
+                            if ui.button("✕").clicked() {
+                                removed = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = removed {
+                        steps.remove(i);
+                        self.recording = None;
+                    }
+                    if ui.button("Add step").clicked() {
+                        steps.push(SequenceStep::default());
+                    }
+                });
+            }
+            Action::Mouse(mouse) => enum_combo(
+                ui,
+                ("mouse", salt),
+                mouse,
+                &[MouseButton::Left, MouseButton::Middle, MouseButton::Right],
+            ),
+            Action::Scroll(direction) => enum_combo(
+                ui,
+                ("scroll", salt),
+                direction,
+                &[
+                    ScrollDirection::Up,
+                    ScrollDirection::Down,
+                    ScrollDirection::Left,
+                    ScrollDirection::Right,
+                ],
+            ),
+            Action::Text(text) => {
+                ui.text_edit_singleline(text);
+            }
+            Action::Command(command) => {
+                ui.add(egui::TextEdit::singleline(command).code_editor().hint_text("shell command"));
+            }
+            Action::SwitchProfile(name) => {
+                egui::ComboBox::from_id_salt(("profile", salt))
+                    .selected_text(name.as_str())
+                    .show_ui(ui, |ui| {
+                        for profile in &self.config.profiles {
+                            ui.selectable_value(name, profile.name.clone(), &profile.name);
+                        }
+                    });
+            }
+            Action::ToggleMode(mode) => enum_combo(
+                ui,
+                ("mode", salt),
+                mode,
+                &[Mode::MediaSlider, Mode::LeftHanded, Mode::TipClick],
+            ),
+            Action::Drag(mode) => enum_combo(
+                ui,
+                ("drag", salt),
+                mode,
+                &[DragMode::Scroll, DragMode::Pan, DragMode::Zoom],
+            ),
+        });
+
+        if ui.add_enabled(action != Action::None, egui::Button::new("Clear")).clicked() {
+            action = Action::None;
+        }
+
+        if action != original {
+            let map = button.group.map(self.config.active_mut());
+            if action == Action::None {
+                map.remove(&button.id);
+            } else {
+                map.insert(button.id, action);
+            }
+        }
+    }
+}
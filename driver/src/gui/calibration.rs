@@ -0,0 +1,113 @@
+use eframe::egui;
+
+use super::ConfigEditor;
+use crate::calibrate::{self, Calibrator};
+use crate::config::{even_media_zones, Calibration, TABLET_MAX_X};
+
+fn calibration_grid(ui: &mut egui::Ui, id: &str, calibration: &Calibration) {
+    egui::Grid::new(id).num_columns(2).striped(true).show(ui, |ui| {
+        let rows = [
+            ("Idle pressure", calibration.pressure_idle.to_string()),
+            ("Touch threshold", calibration.pressure_threshold.to_string()),
+            ("Pressure range", calibration.pressure_range.to_string()),
+            ("X extent", format!("{} .. {}", calibration.x_min, calibration.x_max)),
+            ("Y extent", format!("{} .. {}", calibration.y_min, calibration.y_max)),
+            ("Media strip below Y", calibration.media_strip_y.to_string()),
+        ];
+        for (label, value) in rows {
+            ui.label(label);
+            ui.monospace(value);
+            ui.end_row();
+        }
+    });
+}
+
+impl ConfigEditor {
+    fn media_zones_ui(&mut self, ui: &mut egui::Ui) {
+        let calibration = &mut self.config.calibration;
+        ui.label("Media strip zones, in tablet units from the left. Calibration sets them from a tap on each icon.");
+        ui.horizontal(|ui| {
+            let mut count = calibration.media_zones.len() as u8;
+            ui.label("Icons:");
+            ui.add(egui::DragValue::new(&mut count).range(1..=20));
+            let spread = ui.button("Spread evenly").clicked();
+            if spread || count as usize != calibration.media_zones.len() {
+                calibration.media_zones = even_media_zones(count);
+            }
+            ui.label("Dead band:");
+            ui.add(egui::DragValue::new(&mut calibration.media_dead_band).range(0..=200));
+        });
+        egui::Grid::new("media_zones").striped(true).show(ui, |ui| {
+            for (i, zone) in calibration.media_zones.iter_mut().enumerate() {
+                ui.label(format!("{}", i));
+                ui.add(egui::DragValue::new(&mut zone.x_min).range(0..=TABLET_MAX_X));
+                ui.add(egui::DragValue::new(&mut zone.x_max).range(0..=TABLET_MAX_X));
+                ui.end_row();
+            }
+        });
+    }
+
+    pub(super) fn calibration_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(calibrator) = &self.calibrator else {
+            ui.label("Current calibration:");
+            calibration_grid(ui, "calibration_current", &self.config.calibration);
+            ui.add_space(10.0);
+            ui.label(
+                "Calibration measures your pen's idle and full pressure, the lightest touch \
+                 and the edges of the drawing area. It needs the driver to be running.",
+            );
+            if ui.add_enabled(self.driver.is_some(), egui::Button::new("Start calibration")).clicked() {
+                self.calibrator = Some(Calibrator::new(&self.config.calibration));
+            }
+            if ui.button("Reset to defaults").clicked() {
+                calibrate::apply(&mut self.config, Calibration::default());
+            }
+            ui.separator();
+            self.media_zones_ui(ui);
+            return;
+        };
+
+        if let Some(result) = calibrator.result().cloned() {
+            ui.heading("Calibration finished");
+            calibration_grid(ui, "calibration_result", &result);
+            ui.horizontal(|ui| {
+                if ui.button("Use these values").clicked() {
+                    calibrate::apply(&mut self.config, result);
+                    self.calibrator = None;
+                    self.status_msg = "Calibration applied. Save to keep it.".to_string();
+                }
+                if ui.button("Discard").clicked() {
+                    self.calibrator = None;
+                }
+            });
+            return;
+        }
+
+        ui.heading(calibrator.instructions());
+        if let Some(retry) = calibrator.retry {
+            ui.colored_label(ui.visuals().warn_fg_color, retry);
+        }
+        let (step, total) = calibrator.progress();
+        ui.add(
+            egui::ProgressBar::new((step - 1) as f32 / total as f32)
+                .text(format!("Step {} of {}", step, total)),
+        );
+        match (&self.driver, self.canvas.last_sample) {
+            (None, _) => {
+                ui.colored_label(ui.visuals().error_fg_color, "Lost the connection to the driver.");
+            }
+            (Some(_), Some(sample)) => {
+                ui.label(format!(
+                    "Raw pen: x {}, y {}, pressure {}",
+                    sample.raw_x, sample.raw_y, sample.raw_pressure
+                ));
+            }
+            (Some(_), None) => {
+                ui.label("Waiting for the pen…");
+            }
+        }
+        if ui.button("Cancel").clicked() {
+            self.calibrator = None;
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use eframe::egui;
+
+use super::ConfigEditor;
+use crate::config::{evaluate_curve, CURVE_PRESETS, SENSITIVITY_RANGE};
+
+const CURVE_SIZE: f32 = 280.0;
+/// How close, in screen points, the pointer must be to grab a curve point.
+const GRAB_RADIUS: f32 = 10.0;
+
+impl ConfigEditor {
+    pub(super) fn pressure_ui(&mut self, ui: &mut egui::Ui) {
+        let calibration = self.config.calibration.clone();
+        let profile = self.config.active_mut();
+
+        ui.label("Pressure Threshold:");
+        let mut custom = profile.pressure_threshold.is_some();
+        ui.checkbox(&mut custom, format!(
+            "Override the calibrated threshold ({} levels)",
+            calibration.pressure_threshold
+        ));
+        let mut threshold = profile.threshold(&calibration);
+        ui.add_enabled(custom, egui::Slider::new(
+            &mut threshold,
+            0..=(calibration.pressure_idle - 1).max(0) as u16)
+            .text("levels")
+            .step_by(10.0)
+        );
+        profile.pressure_threshold = custom.then_some(threshold);
+
+        ui.add_space(10.0);
+
+        ui.label("Sensitivity:");
+        ui.add(egui::Slider::new(
+            &mut profile.sensitivity,
+            SENSITIVITY_RANGE)
+            .text("x")
+            .step_by(0.1)
+        );
+
+        ui.add_space(10.0);
+
+        ui.horizontal(|ui| {
+            ui.label("Curve:");
+            for (name, points) in CURVE_PRESETS {
+                if ui.button(name).clicked() {
+                    profile.pressure_curve = points.to_vec();
+                }
+            }
+        });
+        ui.label("Drag points to shape the curve. Double-click to add a point, right-click to remove one.");
+
+        self.curve_editor(ui);
+
+        ui.add_space(10.0);
+        // The margin applies to every profile and has to stay below each threshold.
+        let max_margin = self
+            .config
+            .profiles
+            .iter()
+            .map(|profile| profile.threshold(&calibration).saturating_sub(1))
+            .min()
+            .unwrap_or(0)
+            .min(300);
+        let tip = &mut self.config.tip;
+        tip.release_margin = tip.release_margin.min(max_margin);
+        ui.label("Tip:");
+        ui.add(egui::Slider::new(&mut tip.release_margin, 0..=max_margin).text("release margin"))
+            .on_hover_text("How far below the threshold the pressure must fall to lift the tip");
+        ui.add(egui::Slider::new(&mut tip.min_contact_ms, 0..=100).text("ms minimum contact"))
+            .on_hover_text("Shorter contacts are ignored");
+
+        ui.add_space(10.0);
+        let drift = &mut self.config.pressure_drift;
+        ui.checkbox(&mut drift.enabled, "Compensate pressure drift")
+            .on_hover_text("Follows the pen's idle pressure while it hovers");
+        ui.add_enabled_ui(drift.enabled, |ui| {
+            ui.add(egui::Slider::new(&mut drift.max_drift, 0..=1000).text("max drift"));
+            ui.add(egui::Slider::new(&mut drift.rate, 0.0..=1.0).text("adapt rate per second"));
+            ui.add(egui::Slider::new(&mut drift.noise_band, 0..=200).text("noise band"))
+                .on_hover_text("Hover readings further from the baseline don't move it");
+        });
+    }
+
+    fn curve_editor(&mut self, ui: &mut egui::Ui) {
+        let (response, painter) = ui.allocate_painter(
+            egui::vec2(CURVE_SIZE, CURVE_SIZE),
+            egui::Sense::click_and_drag(),
+        );
+        let rect = response.rect.shrink(GRAB_RADIUS);
+        let to_screen = |[x, y]: [f32; 2]| {
+            egui::pos2(rect.left() + x * rect.width(), rect.bottom() - y * rect.height())
+        };
+        let from_screen = |pos: egui::Pos2| {
+            [
+                ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0),
+                ((rect.bottom() - pos.y) / rect.height()).clamp(0.0, 1.0),
+            ]
+        };
+
+        // Where the pen currently is on the curve, if the driver is sending samples.
+        let live_input = self
+            .canvas
+            .last_sample
+            .and_then(|sample| {
+                self.config.active().curve_input(&self.config.calibration, sample.raw_pressure)
+            });
+
+        let curve = &mut self.config.active_mut().pressure_curve;
+        let nearest = response.hover_pos().and_then(|pos| {
+            curve
+                .iter()
+                .enumerate()
+                .map(|(i, point)| (i, to_screen(*point).distance(pos)))
+                .filter(|(_, distance)| *distance < GRAB_RADIUS)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(i, _)| i)
+        });
+
+        if response.drag_started() {
+            self.curve_drag = nearest;
+        }
+        if response.drag_stopped() {
+            self.curve_drag = None;
+        }
+        if let (Some(i), Some(pos)) = (self.curve_drag, response.interact_pointer_pos())
+            && i < curve.len()
+        {
+            let [mut x, y] = from_screen(pos);
+            // The ends stay at input 0 and 1, the others between their neighbours.
+            if i == 0 {
+                x = 0.0;
+            } else if i == curve.len() - 1 {
+                x = 1.0;
+            } else {
+                x = x.clamp(curve[i - 1][0], curve[i + 1][0]);
+            }
+            curve[i] = [x, y];
+        }
+        if response.double_clicked() && nearest.is_none()
+            && let Some(pos) = response.interact_pointer_pos()
+        {
+            let point = from_screen(pos);
+            let index = curve.iter().position(|p| p[0] > point[0]).unwrap_or(curve.len());
+            if index > 0 && index < curve.len() {
+                curve.insert(index, point);
+            }
+        }
+        if response.secondary_clicked()
+            && let Some(i) = nearest.filter(|&i| i > 0 && i < curve.len() - 1)
+        {
+            curve.remove(i);
+        }
+
+        let visuals = ui.visuals();
+        painter.rect_filled(response.rect, 4.0, visuals.extreme_bg_color);
+        let grid = egui::Stroke::new(1.0, visuals.faint_bg_color);
+        for step in 1..4 {
+            let t = step as f32 / 4.0;
+            painter.line_segment([to_screen([t, 0.0]), to_screen([t, 1.0])], grid);
+            painter.line_segment([to_screen([0.0, t]), to_screen([1.0, t])], grid);
+        }
+        painter.line_segment(
+            [to_screen([0.0, 0.0]), to_screen([1.0, 1.0])],
+            egui::Stroke::new(1.0, visuals.weak_text_color()),
+        );
+        painter.add(egui::Shape::line(
+            curve.iter().map(|point| to_screen(*point)).collect(),
+            egui::Stroke::new(2.0, visuals.strong_text_color()),
+        ));
+        for (i, point) in curve.iter().enumerate() {
+            let active = Some(i) == self.curve_drag || Some(i) == nearest;
+            painter.circle_filled(
+                to_screen(*point),
+                if active { 6.0 } else { 4.0 },
+                visuals.selection.bg_fill,
+            );
+        }
+
+        if let Some(input) = live_input {
+            let marker = to_screen([input, evaluate_curve(curve, input)]);
+            painter.circle_stroke(marker, 7.0, egui::Stroke::new(2.0, visuals.warn_fg_color));
+            ui.ctx().request_repaint();
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+
+use super::ConfigEditor;
+use crate::control::{self, DriverStatus};
+use crate::physical_device::{PhysicalDevice, UsbLocation};
+
+const UINPUT_PATH: &str = "/dev/uinput";
+/// How often device nodes are probed while the panel is open.
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What the panel shows besides the driver's own status. The checks run as
+/// the GUI's user, which may differ from the driver's.
+#[derive(Default)]
+pub struct Diagnostics {
+    pub driver_status: Option<DriverStatus>,
+    /// Found by the GUI itself, for when the driver isn't running.
+    usb: Option<UsbLocation>,
+    uinput_writable: bool,
+    usb_writable: Option<bool>,
+    last_check: Option<Instant>,
+}
+
+fn is_writable(path: &Path) -> bool {
+    OpenOptions::new().write(true).open(path).is_ok()
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
+
+impl Diagnostics {
+    fn refresh(&mut self) {
+        if self.last_check.is_some_and(|check| check.elapsed() < CHECK_INTERVAL) {
+            return;
+        }
+        self.last_check = Some(Instant::now());
+        self.usb = PhysicalDevice::locate(crate::VID, crate::PID).ok();
+        self.uinput_writable = is_writable(Path::new(UINPUT_PATH));
+        self.usb_writable = self.location().map(|location| is_writable(&location.node()));
+    }
+
+    fn location(&self) -> Option<&UsbLocation> {
+        self.driver_status
+            .as_ref()
+            .and_then(|status| status.device.as_ref())
+            .map(|device| &device.location)
+            .or(self.usb.as_ref())
+    }
+}
+
+impl ConfigEditor {
+    pub(super) fn status_ui(&mut self, ui: &mut egui::Ui) {
+        let diagnostics = &mut self.diagnostics;
+        diagnostics.refresh();
+        let reachable = self.driver.is_some();
+        let status = diagnostics.driver_status.as_ref().filter(|_| reachable);
+        let device = status.and_then(|status| status.device.as_ref());
+
+        egui::Grid::new("diagnostics").num_columns(2).striped(true).show(ui, |ui| {
+            ui.label("Driver reachable");
+            ui.label(format!("{} ({})", yes_no(reachable), control::socket_path().display()));
+            ui.end_row();
+
+            ui.label("Device connected");
+            ui.label(match (reachable, device) {
+                (false, _) => "unknown",
+                (true, device) => yes_no(device.is_some()),
+            });
+            ui.end_row();
+
+            ui.label("USB bus / port");
+            ui.label(match diagnostics.location() {
+                Some(location) => format!(
+                    "bus {:03}, port {}, device {:03}",
+                    location.bus,
+                    location.port_path(),
+                    location.address
+                ),
+                None => "tablet not found".to_string(),
+            });
+            ui.end_row();
+
+            ui.label("Endpoint");
+            ui.label(match device {
+                Some(device) => format!("0x{:02x}", device.endpoint),
+                None => "—".to_string(),
+            });
+            ui.end_row();
+
+            ui.label("Active profile");
+            ui.label(status.map_or("—", |status| status.active_profile.as_str()));
+            ui.end_row();
+
+            ui.label("Report rate");
+            ui.label(match status {
+                Some(status) if device.is_some() => format!("{:.0} Hz", status.report_rate),
+                _ => "—".to_string(),
+            });
+            ui.end_row();
+
+            ui.label("Pressure drift");
+            ui.label(match status {
+                Some(status) if device.is_some() => format!("{:+} raw levels", status.pressure_drift),
+                _ => "—".to_string(),
+            });
+            ui.end_row();
+
+            ui.label("Last error");
+            match status.and_then(|status| status.last_error.as_ref()) {
+                Some(error) => ui.colored_label(ui.visuals().error_fg_color, error),
+                None => ui.label("none"),
+            };
+            ui.end_row();
+
+            ui.label(format!("{} writable", UINPUT_PATH));
+            ui.label(yes_no(diagnostics.uinput_writable));
+            ui.end_row();
+
+            ui.label("USB node writable");
+            ui.label(match (diagnostics.location(), diagnostics.usb_writable) {
+                (Some(location), Some(writable)) => {
+                    format!("{} ({})", yes_no(writable), location.node().display())
+                }
+                _ => "—".to_string(),
+            });
+            ui.end_row();
+        });
+
+        ui.add_space(10.0);
+        ui.label(
+            "Write access is checked for the user running this window. The driver needs both \
+             nodes writable, usually through a udev rule or by running it as root.",
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use eframe::egui;
+
+use super::ConfigEditor;
+use crate::config::{MAX_PRESSURE, TABLET_MAX_X, TABLET_MAX_Y};
+use crate::control::PenSample;
+
+/// Oldest strokes are dropped once the canvas holds this many points.
+const MAX_CANVAS_POINTS: usize = 20_000;
+
+/// Pen reports collected from the driver for the test canvas.
+#[derive(Default)]
+pub struct TestCanvas {
+    pub last_sample: Option<PenSample>,
+    strokes: Vec<Vec<PenSample>>,
+    pen_down: bool,
+}
+
+impl TestCanvas {
+    pub fn record(&mut self, sample: PenSample) {
+        self.last_sample = Some(sample);
+        if sample.media_strip || sample.pressure <= 0 {
+            self.pen_down = false;
+            return;
+        }
+        if !self.pen_down {
+            self.strokes.push(Vec::new());
+            self.pen_down = true;
+        }
+        if let Some(stroke) = self.strokes.last_mut() {
+            stroke.push(sample);
+        }
+        while self.strokes.iter().map(Vec::len).sum::<usize>() > MAX_CANVAS_POINTS {
+            self.strokes.remove(0);
+        }
+    }
+}
+
+fn stroke_width(pressure: i32) -> f32 {
+    1.0 + 12.0 * (pressure as f32 / MAX_PRESSURE as f32).clamp(0.0, 1.0)
+}
+
+impl ConfigEditor {
+    pub(super) fn test_ui(&mut self, ui: &mut egui::Ui) {
+        if self.driver.is_none() {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "The driver is not running. Start v1060p-driver to see live pen data.",
+            );
+        }
+
+        match self.canvas.last_sample {
+            Some(sample) => {
+                egui::Grid::new("pen_sample").show(ui, |ui| {
+                    ui.label("Position:");
+                    ui.monospace(format!("raw {:>5}, {:>5}   →   {:>5}, {:>5}",
+                        sample.raw_x, sample.raw_y, sample.x, sample.y));
+                    ui.end_row();
+                    ui.label("Pressure:");
+                    ui.monospace(format!("raw {:>5}   →   {:>5} ({:.0}%)",
+                        sample.raw_pressure, sample.pressure,
+                        100.0 * sample.pressure as f32 / MAX_PRESSURE as f32));
+                    ui.end_row();
+                    ui.label("Area:");
+                    ui.label(if sample.media_strip { "media strip" } else { "drawing area" });
+                    ui.end_row();
+                });
+            }
+            None => {
+                ui.label("Move the pen over the tablet.");
+            }
+        }
+
+        let width = ui.available_width();
+        let height = width * TABLET_MAX_Y as f32 / TABLET_MAX_X as f32;
+        let (response, painter) =
+            ui.allocate_painter(egui::vec2(width, height.min(400.0)), egui::Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 4.0, ui.visuals().extreme_bg_color);
+
+        let to_screen = |sample: &PenSample| {
+            rect.min + egui::vec2(
+                sample.x as f32 / TABLET_MAX_X as f32 * rect.width(),
+                sample.y as f32 / TABLET_MAX_Y as f32 * rect.height(),
+            )
+        };
+        let color = ui.visuals().strong_text_color();
+        for stroke in &self.canvas.strokes {
+            for pair in stroke.windows(2) {
+                painter.line_segment(
+                    [to_screen(&pair[0]), to_screen(&pair[1])],
+                    egui::Stroke::new(stroke_width(pair[1].pressure), color),
+                );
+            }
+        }
+        if let Some(sample) = self.canvas.last_sample.filter(|s| !s.media_strip) {
+            painter.circle_stroke(
+                to_screen(&sample),
+                stroke_width(sample.pressure) / 2.0 + 3.0,
+                egui::Stroke::new(1.0, ui.visuals().selection.bg_fill),
+            );
+        }
+
+        if ui.button("Clear canvas").clicked() {
+            self.canvas.strokes.clear();
+        }
+
+        ui.ctx().request_repaint();
+    }
+}
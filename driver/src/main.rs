@@ -2,27 +2,41 @@ mod virtual_device;
 mod physical_device;
 mod config;
 mod gui;
+mod focus;
+mod control;
+mod monitors;
+mod calibrate;
+mod drift;
 
 use clap::Parser;
 use signal_hook::consts::signal::*;
 use signal_hook::flag::register;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
 use std::sync::{Arc, RwLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use physical_device::PhysicalDevice;
 use virtual_device::{DeviceDispatcher, RawDataReader};
 use config::AppConfig;
+use control::{ClientRequest, ControlServer, DeviceInfo, DriverEvent, DriverStatus};
+use std::ffi::OsStr;
 use std::fs;
+use inotify::{Event, Inotify, WatchMask};
 
 const VID: u16 = 0x08f2;
 const PID: u16 = 0x6811;
+const CONFIG_DEBOUNCE: Duration = Duration::from_millis(200);
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)] 
 struct Args {
     #[arg(short, long)]
     config: bool,
+    /// Measure the pen and tablet with the running driver and save the results.
+    #[arg(long)]
+    calibrate: bool,
 }
 
 fn main() {
@@ -36,49 +50,38 @@ fn main() {
         return;
     }
 
-    let initial_config = AppConfig::load();
-    println!("Loaded config: Threshold={}, Sensitivity={}", 
-            initial_config.pressure_threshold, initial_config.sensitivity);
-    
-    let config = Arc::new(RwLock::new(initial_config));
-
-    let config_monitor = config.clone();
-    thread::spawn(move || {
-        let path = AppConfig::get_config_path();
-        let mut last_mtime = fs::metadata(&path)
-            .and_then(|m| m.modified())
-            .ok();
+    if args.calibrate {
+        if let Err(e) = calibrate::run_cli() {
+            eprintln!("{}", e);
+        }
+        return;
+    }
 
-        loop {
-            thread::sleep(Duration::from_millis(1000));
-            
-            if let Ok(metadata) = fs::metadata(&path) {
-                if let Ok(mtime) = metadata.modified() {
-                    let changed = match last_mtime {
-                        None => true,
-                        Some(last) => mtime > last,
-                    };
+    let (request_sender, requests) = mpsc::channel();
+    let control = ControlServer::start(request_sender).unwrap_or_else(|e| {
+        eprintln!("Error starting control channel at {}: {}", control::socket_path().display(), e);
+        ControlServer::default()
+    });
 
-                    if changed {
-                        println!("Config file changed, reloading...");
-                        let new_config = AppConfig::load();
-                        println!("New config: Threshold={}, Sensitivity={}", 
-                             new_config.pressure_threshold, new_config.sensitivity);
-                        
-                        if let Ok(mut w) = config_monitor.write() {
-                            *w = new_config;
-                        }
-                        last_mtime = Some(mtime);
-                    }
-                }
-            }
-        }
+    let initial_config = load_config(&control).unwrap_or_else(|| {
+        eprintln!("Using default settings.");
+        AppConfig::default()
     });
+    let profile = initial_config.active();
+    println!("Loaded config: Profile={}, Threshold={}, Sensitivity={}",
+            profile.name, profile.threshold(&initial_config.calibration), profile.sensitivity);
+    
+    let config = Arc::new(RwLock::new(initial_config));
+
+    let config_replaced = focus::spawn_profile_switcher(config.clone());
+    spawn_config_watcher(config.clone(), control.clone(), config_replaced.clone());
+    spawn_request_handler(requests, config.clone(), control.clone(), config_replaced);
 
     let mut data_reader = RawDataReader::new();
-    let mut device_dispatcher = DeviceDispatcher::new(config.clone());
+    let mut device_dispatcher = DeviceDispatcher::new(config.clone(), control.clone());
 
     let mut physical_device: Option<PhysicalDevice> = None;
+    let mut status = StatusReporter::new(control.clone(), config.clone());
 
     println!("Driver started. Waiting for device...");
 
@@ -87,34 +90,245 @@ fn main() {
             if let Some(device) = &mut physical_device {
                 match device.read_device_responses(&mut data_reader.data) {
                     Ok(len) if len > 0 => {
+                        status.report_received();
                         device_dispatcher.dispatch(&data_reader);
-                        if device_dispatcher.syn().is_err() {
+                        status.pressure_drift(device_dispatcher.pressure_drift());
+                        if let Err(e) = device_dispatcher.syn() {
                             eprintln!("Error emitting SYN.");
+                            status.error(format!("Error emitting SYN: {}", e));
                         }
                     }
                     Ok(_) => {}
                     Err(rusb::Error::Timeout) => {}
                     Err(e) => {
                         eprintln!("Device error/disconnected: {}", e);
+                        status.error(format!("Device error/disconnected: {}", e));
+                        status.disconnected();
                         physical_device = None;
                     }
                 }
+                device_dispatcher.tick();
             } else {
                 match PhysicalDevice::new(VID, PID) {
                     Ok(mut dev) => {
                         println!("Device connected!");
                         dev.init().set_full_mode();
+                        status.connected(DeviceInfo {
+                            location: dev.location(),
+                            endpoint: dev.endpoint_address(),
+                        });
                         physical_device = Some(dev);
                     }
-                    Err(_) => {
+                    Err(e) => {
+                        if e != rusb::Error::NoDevice {
+                            status.error(format!("Error opening device: {}", e));
+                        }
                         thread::sleep(Duration::from_millis(500));
                     }
                 }
             }
+            status.tick();
+        }
+    });
+}
+
+/// Tracks the driver's health and sends it to control clients every `STATUS_INTERVAL`.
+struct StatusReporter {
+    control: ControlServer,
+    config: Arc<RwLock<AppConfig>>,
+    status: DriverStatus,
+    reports: u32,
+    since: Instant,
+}
+
+impl StatusReporter {
+    fn new(control: ControlServer, config: Arc<RwLock<AppConfig>>) -> Self {
+        Self {
+            control,
+            config,
+            status: DriverStatus::default(),
+            reports: 0,
+            since: Instant::now(),
+        }
+    }
+
+    fn report_received(&mut self) {
+        self.reports += 1;
+    }
+
+    fn pressure_drift(&mut self, drift: i32) {
+        self.status.pressure_drift = drift;
+    }
+
+    fn connected(&mut self, device: DeviceInfo) {
+        self.status.device = Some(device);
+        self.send();
+    }
+
+    fn disconnected(&mut self) {
+        self.status.device = None;
+        self.send();
+    }
+
+    fn error(&mut self, message: String) {
+        self.status.last_error = Some(message);
+    }
+
+    fn tick(&mut self) {
+        if self.since.elapsed() >= STATUS_INTERVAL {
+            self.send();
+        }
+    }
+
+    fn send(&mut self) {
+        self.status.report_rate = self.reports as f32 / self.since.elapsed().as_secs_f32();
+        self.status.active_profile = self.config.read().unwrap().active().name.clone();
+        self.reports = 0;
+        self.since = Instant::now();
+        self.control.broadcast(&DriverEvent::Status(self.status.clone()));
+    }
+}
+
+/// Reloads the config whenever `settings.json` or `settings.toml` is rewritten in place or an editor
+/// renames a new copy over it. Changes are applied once the file has been quiet
+/// for `CONFIG_DEBOUNCE`, so a burst of writes is only read once.
+fn spawn_config_watcher(
+    config: Arc<RwLock<AppConfig>>,
+    control: ControlServer,
+    config_replaced: Arc<AtomicBool>,
+) {
+    let path = AppConfig::get_config_path();
+    let Some(dir) = path.parent() else {
+        return;
+    };
+
+    let watch = fs::create_dir_all(dir).and_then(|_| {
+        let inotify = Inotify::init()?;
+        inotify.watches().add(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+        Ok(inotify)
+    });
+    let mut inotify = match watch {
+        Ok(inotify) => inotify,
+        Err(e) => {
+            eprintln!("Error watching {}: {}. Config changes need a driver restart.", dir.display(), e);
+            return;
+        }
+    };
+
+    thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        let mut is_config = |event: Event<&OsStr>| event.name.is_some_and(AppConfig::is_config_file_name);
+
+        loop {
+            let changed = match inotify.read_events_blocking(&mut buffer) {
+                Ok(mut events) => events.any(&mut is_config),
+                Err(e) => {
+                    eprintln!("Error watching config: {}", e);
+                    return;
+                }
+            };
+            if !changed {
+                continue;
+            }
+
+            loop {
+                thread::sleep(CONFIG_DEBOUNCE);
+                let changed_again = inotify
+                    .read_events(&mut buffer)
+                    .map(|mut events| events.any(&mut is_config))
+                    .unwrap_or(false);
+                if !changed_again {
+                    break;
+                }
+            }
+
+            println!("Config file changed, reloading...");
+            if let Some(new_config) = load_config(&control) {
+                let profile = new_config.active();
+                println!("New config: Profile={}, Threshold={}, Sensitivity={}",
+                     profile.name, profile.threshold(&new_config.calibration), profile.sensitivity);
+
+                if let Ok(mut w) = config.write() {
+                    *w = new_config;
+                }
+                config_replaced.store(true, Ordering::Relaxed);
+            } else {
+                eprintln!("Keeping the current config.");
+            }
+        }
+    });
+}
+
+/// Applies settings previewed by a client without saving them, until the
+/// client reverts, disconnects or the config file changes.
+fn spawn_request_handler(
+    requests: Receiver<ClientRequest>,
+    config: Arc<RwLock<AppConfig>>,
+    control: ControlServer,
+    config_replaced: Arc<AtomicBool>,
+) {
+    thread::spawn(move || {
+        for request in requests {
+            let new_config = match request {
+                ClientRequest::Preview(preview) => {
+                    let mut validation = preview.validate();
+                    // Anyone on the control socket may preview, so only commands
+                    // already in the running settings can run.
+                    let new_commands: Vec<String> = {
+                        let current = config.read().unwrap();
+                        let known = current.commands();
+                        preview.commands().difference(&known).map(|c| c.to_string()).collect()
+                    };
+                    for command in new_commands {
+                        validation.errors.push(format!(
+                            "Command \"{}\" is new and only runs once the settings are saved",
+                            command
+                        ));
+                    }
+                    if !validation.is_valid() {
+                        for message in validation.errors {
+                            control.broadcast(&DriverEvent::ConfigIssue { error: true, message });
+                        }
+                        continue;
+                    }
+                    *preview
+                }
+                ClientRequest::Revert => {
+                    println!("Reverting to the saved config.");
+                    match load_config(&control) {
+                        Some(saved) => saved,
+                        None => continue,
+                    }
+                }
+            };
+            if let Ok(mut w) = config.write() {
+                *w = new_config;
+            }
+            config_replaced.store(true, Ordering::Relaxed);
         }
     });
 }
 
+/// Loads and validates the config file, reporting problems to the log and the
+/// control channel. Returns `None` if the file can't be used.
+fn load_config(control: &ControlServer) -> Option<AppConfig> {
+    match AppConfig::try_load() {
+        Ok(config) => {
+            for warning in config.validate().warnings {
+                eprintln!("Config warning: {}", warning);
+                control.broadcast(&DriverEvent::ConfigIssue { error: false, message: warning });
+            }
+            Some(config)
+        }
+        Err(e) => {
+            let message = format!("Error loading {}: {}", AppConfig::get_config_path().display(), e);
+            eprintln!("{}", message);
+            control.broadcast(&DriverEvent::ConfigIssue { error: true, message });
+            None
+        }
+    }
+}
+
 fn main_loop(mut f: impl FnMut()) {
     let signals: Vec<i32> = vec![SIGINT, SIGTERM, SIGQUIT];
     let flag = Arc::new(AtomicBool::new(false));
@@ -126,6 +340,7 @@ fn main_loop(mut f: impl FnMut()) {
     while !flag.load(Ordering::Relaxed) {
         f();
     }
+    let _ = fs::remove_file(control::socket_path());
     println!();
     println!("The driver has exited.")
 }
\ No newline at end of file
@@ -0,0 +1,52 @@
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::ConnectionExt as _;
+
+/// A monitor in desktop pixel coordinates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Monitor {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Active monitors as reported by RandR. Pure Wayland sessions without
+/// XWayland have no portable equivalent, so this may come back empty.
+pub fn detect() -> Result<Vec<Monitor>, String> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+    let root = conn.setup().roots[screen_num].root;
+    let reply = conn
+        .randr_get_monitors(root, true)
+        .map_err(|e| e.to_string())?
+        .reply()
+        .map_err(|e| e.to_string())?;
+
+    let mut monitors = Vec::new();
+    for info in reply.monitors {
+        let name = conn
+            .get_atom_name(info.name)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map(|reply| String::from_utf8_lossy(&reply.name).into_owned())
+            .unwrap_or_default();
+        monitors.push(Monitor {
+            name,
+            x: info.x.into(),
+            y: info.y.into(),
+            width: info.width.into(),
+            height: info.height.into(),
+        });
+    }
+    Ok(monitors)
+}
+
+/// Bounding box of all monitors as `(x, y, width, height)`.
+pub fn desktop_bounds(monitors: &[Monitor]) -> Option<(i32, i32, i32, i32)> {
+    let x_min = monitors.iter().map(|m| m.x).min()?;
+    let y_min = monitors.iter().map(|m| m.y).min()?;
+    let x_max = monitors.iter().map(|m| m.x + m.width).max()?;
+    let y_max = monitors.iter().map(|m| m.y + m.height).max()?;
+    Some((x_min, y_min, x_max - x_min, y_max - y_min))
+}
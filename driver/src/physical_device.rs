@@ -3,8 +3,41 @@ use rusb::{
     InterfaceDescriptor, TransferType,
 };
 
+use serde::{Deserialize, Serialize};
+
+use std::path::PathBuf;
 use std::time::Duration;
 
+/// Where the tablet sits on the USB bus.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UsbLocation {
+    pub bus: u8,
+    pub address: u8,
+    /// Hub ports from the root hub down to the tablet.
+    pub ports: Vec<u8>,
+}
+
+impl UsbLocation {
+    fn of(device: &Device<GlobalContext>) -> Self {
+        Self {
+            bus: device.bus_number(),
+            address: device.address(),
+            ports: device.port_numbers().unwrap_or_default(),
+        }
+    }
+
+    /// The usbfs node the driver opens.
+    pub fn node(&self) -> PathBuf {
+        PathBuf::from(format!("/dev/bus/usb/{:03}/{:03}", self.bus, self.address))
+    }
+
+    /// Port path as used in sysfs, e.g. `1-2.3`.
+    pub fn port_path(&self) -> String {
+        let ports: Vec<String> = self.ports.iter().map(|port| port.to_string()).collect();
+        format!("{}-{}", self.bus, ports.join("."))
+    }
+}
+
 pub struct PhysicalDevice {
     device: Device<GlobalContext>,
     device_handle: DeviceHandle<GlobalContext>,
@@ -12,6 +45,9 @@ pub struct PhysicalDevice {
 }
 
 impl PhysicalDevice {
+    /// Short, so button gestures and key repeats run on time while the tablet sends nothing.
+    const READ_TIMEOUT: Duration = Duration::from_millis(20);
+
     pub fn new(vid: u16, pid: u16) -> Result<Self, RusbError> {
         let device = Self::get_target_device(vid, pid)?; 
         
@@ -24,6 +60,20 @@ impl PhysicalDevice {
         })
     }
 
+    /// Finds the tablet without opening it.
+    pub fn locate(vid: u16, pid: u16) -> Result<UsbLocation, RusbError> {
+        Self::get_target_device(vid, pid).map(|device| UsbLocation::of(&device))
+    }
+
+    pub fn location(&self) -> UsbLocation {
+        UsbLocation::of(&self.device)
+    }
+
+    /// Interrupt endpoint picked by `init`, 0 before that.
+    pub fn endpoint_address(&self) -> u8 {
+        self.endpoint_address
+    }
+
     pub fn init(&mut self) -> &mut Self {
         let _ = self.device_handle.set_auto_detach_kernel_driver(true);
 
@@ -54,7 +104,7 @@ impl PhysicalDevice {
 
     pub fn read_device_responses(&self, buffer: &mut [u8]) -> Result<usize, RusbError> {
         self.device_handle
-            .read_interrupt(self.endpoint_address, buffer, Duration::from_secs(1))
+            .read_interrupt(self.endpoint_address, buffer, Self::READ_TIMEOUT)
     }
 
     pub fn set_full_mode(&mut self) -> &mut Self {
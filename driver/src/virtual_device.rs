@@ -1,14 +1,34 @@
-use std::collections::HashMap;
 use std::io::Error;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use evdev::{
-    AbsInfo, AbsoluteAxisType, AttributeSet, EventType, InputEvent, Key, Synchronization,
-    UinputAbsSetup,
+    AbsInfo, AbsoluteAxisType, AttributeSet, EventType, InputEvent, Key, RelativeAxisType,
+    Synchronization, UinputAbsSetup,
     uinput::{VirtualDevice, VirtualDeviceBuilder},
 };
 
-use crate::config::AppConfig;
+use crate::config::{
+    AppConfig, MediaStripMode, PenMode, SliderAction, MAX_PRESSURE, TABLET_BUTTON_IDS,
+    TABLET_MAX_X, TABLET_MAX_Y,
+};
+use crate::control::{ControlServer, DriverEvent, PenSample};
+use crate::drift::BaselineTracker;
+
+mod actions;
+mod dead_zone;
+mod drag;
+mod gestures;
+mod macros;
+mod pen_buttons;
+mod repeat;
+
+use actions::ButtonSource;
+use dead_zone::DeadZone;
+use drag::PenDrag;
+use gestures::GestureDetector;
+use macros::MacroPlayer;
+use repeat::HeldKeys;
 
 #[derive(Default)]
 pub struct RawDataReader {
@@ -62,7 +82,7 @@ impl RawDataReader {
         let idx_l = Self::TABLET_BUTTONS_LOW;
 
         if idx_h >= self.data.len() || idx_l >= self.data.len() {
-            return 0 | (0xcc << 8);
+            return 0xcc << 8;
         }
 
         self.u16_from_2_u8(self.data[idx_h], self.data[idx_l]) | (0xcc << 8)
@@ -75,111 +95,111 @@ impl RawDataReader {
 
 pub struct DeviceDispatcher {
     config: Arc<RwLock<AppConfig>>,
+    control: ControlServer,
     tablet_last_raw_pressed_buttons: u16,
-    pen_last_raw_pressed_button: u8,
-    last_pressed_media_button: u8,
-    media_button_id_to_key_code_map: HashMap<u8, Vec<Key>>,
-    tablet_button_id_to_key_code_map: HashMap<u8, Vec<Key>>,
-    pen_button_id_to_key_code_map: HashMap<u8, Vec<Key>>,
+    /// Stylus buttons down at the last report, as decoded by `pen_buttons::decode`.
+    pen_buttons_down: u8,
+    last_pressed_media_button: Option<u8>,
     virtual_pen: VirtualDevice,
     virtual_keyboard: VirtualDevice,
     media_keyboard: VirtualDevice,
+    virtual_mouse: VirtualDevice,
     was_touching: bool,
+    /// The tip of the virtual pen is down (`BTN_TOUCH`).
+    touch_down: bool,
+    /// Start of the current contact, which may not be reported yet.
+    contact_since: Option<Instant>,
     last_x: f32,
     last_y: f32,
-    media_button_width: i32,
+    pressure_baseline: BaselineTracker,
+    /// Strip position of the last slider step while the pen drags along the media strip.
+    slider_anchor: Option<i32>,
+    macros: MacroPlayer,
+    gestures: GestureDetector,
+    /// `layer_shift_button` is held.
+    layer_shifted: bool,
+    /// Tablet buttons pressed while the layer was shifted, one bit per id.
+    shifted_tablet_buttons: u16,
+    /// The media button in `last_pressed_media_button` was pressed while shifted.
+    media_shifted: bool,
+    drag: Option<PenDrag>,
+    repeating: Option<HeldKeys>,
+    dead_zone: DeadZone,
 }
 
 impl DeviceDispatcher {
     const PRESSED: i32 = 1;
     const RELEASED: i32 = 0;
-    const HOLD: i32 = 2;
-    const MAX_X: i32 = 4095;
-    const MAX_Y: i32 = 4095;
-    const MAX_PRESSURE: i32 = 8191;
-    const RAW_PRESSURE_POINTS: i32 = 2000;
-    const MEDIA_BUTTONS_COUNT: i32 = 10;
-
-    pub fn new(config: Arc<RwLock<AppConfig>>) -> Self {
-        let default_media_button_id_to_key_code_map: HashMap<u8, Vec<Key>> = [
-            (0, vec![Key::KEY_MUTE]),
-            (1, vec![Key::KEY_VOLUMEDOWN]),
-            (2, vec![Key::KEY_VOLUMEUP]),
-            (3, vec![Key::KEY_PLAYER]),
-            (4, vec![Key::KEY_PLAYPAUSE]),
-            (5, vec![Key::KEY_PREVIOUSSONG]),
-            (6, vec![Key::KEY_NEXTSONG]),
-            (7, vec![Key::KEY_HOME]),
-            (8, vec![Key::KEY_CALC]),
-            (9, vec![Key::KEY_LEFTMETA, Key::KEY_D]),
-        ]
-        .iter()
-        .cloned()
-        .collect();
-
-        let default_tablet_button_id_to_key_code_map: HashMap<u8, Vec<Key>> = [
-            (0, vec![Key::KEY_TAB]),                        // TAB
-            (1, vec![Key::KEY_SPACE]),                      // SPACE
-            (2, vec![Key::KEY_LEFTALT]),                    // ALT
-            (3, vec![Key::KEY_LEFTCTRL]),                   // CTRL
-            (4, vec![Key::KEY_PAGEUP]),                     // MOUSE UP
-            (5, vec![Key::KEY_PAGEDOWN]),                   // MOUSE DOWN
-            (6, vec![Key::KEY_LEFTBRACE]),                  // MOUSE AREA -
-            (7, vec![Key::KEY_LEFTCTRL, Key::KEY_KPMINUS]), // CTRL- ZOOM
-            (8, vec![Key::KEY_LEFTCTRL, Key::KEY_KPPLUS]),  // CTRL+ ZOOM
-            (9, vec![Key::KEY_ESC]),                        // ESC CANCEL
-            (12, vec![Key::KEY_B]),                         // TOGGLE MOUSE/TABLET
-            (13, vec![Key::KEY_RIGHTBRACE]),                // MOUSE AREA +
-        ]
-        .iter()
-        .cloned()
-        .collect();
-
-        let default_pen_button_id_to_key_code_map: HashMap<u8, Vec<Key>> =
-            [(4, vec![Key::BTN_STYLUS]), (6, vec![Key::BTN_STYLUS2])]
-                .iter()
-                .cloned()
-                .collect();
+    const MAX_X: i32 = TABLET_MAX_X;
+    const MAX_Y: i32 = TABLET_MAX_Y;
+    const MAX_PRESSURE: i32 = MAX_PRESSURE;
+    const PEN_KEYS: [Key; 4] = [
+        Key::BTN_STYLUS,
+        Key::BTN_STYLUS2,
+        Key::BTN_MIDDLE,
+        Key::BTN_RIGHT,
+    ];
+
+    pub fn new(config: Arc<RwLock<AppConfig>>, control: ControlServer) -> Self {
+        let keyboard_keys = actions::keyboard_keys();
 
         DeviceDispatcher {
-            config: config,
+            config,
+            control,
             tablet_last_raw_pressed_buttons: 0xFFFF,
-            pen_last_raw_pressed_button: 0,
-            last_pressed_media_button: 0,
-            media_button_id_to_key_code_map: default_media_button_id_to_key_code_map.clone(),
-            tablet_button_id_to_key_code_map: default_tablet_button_id_to_key_code_map.clone(),
-            pen_button_id_to_key_code_map: default_pen_button_id_to_key_code_map.clone(),
-            virtual_pen: Self::virtual_pen_builder(
-                &default_pen_button_id_to_key_code_map
-                    .values()
-                    .flatten()
-                    .cloned()
-                    .collect::<Vec<Key>>(),
-            )
-            .expect("Error building virtual pen"),
-            virtual_keyboard: Self::virtual_keyboard_builder(
-                &default_tablet_button_id_to_key_code_map
-                    .values()
-                    .flatten()
-                    .cloned()
-                    .collect::<Vec<Key>>(),
-            )
-            .expect("Error building virtual keyboard"),
-            media_keyboard: Self::virtual_keyboard_builder(
-                &default_media_button_id_to_key_code_map
-                    .values()
-                    .flatten()
-                    .cloned()
-                    .collect::<Vec<Key>>(),
-            )
-            .expect("Error building media keyboard"),
+            pen_buttons_down: 0,
+            last_pressed_media_button: None,
+            virtual_pen: Self::virtual_pen_builder(&Self::PEN_KEYS)
+                .expect("Error building virtual pen"),
+            virtual_keyboard: Self::virtual_keyboard_builder(&keyboard_keys)
+                .expect("Error building virtual keyboard"),
+            media_keyboard: Self::virtual_keyboard_builder(&keyboard_keys)
+                .expect("Error building media keyboard"),
+            virtual_mouse: Self::virtual_mouse_builder().expect("Error building virtual mouse"),
             was_touching: false,
+            touch_down: false,
+            contact_since: None,
             last_x: (Self::MAX_X / 2) as f32,
             last_y: (Self::MAX_Y / 2) as f32,
-            media_button_width: Self::MAX_X / Self::MEDIA_BUTTONS_COUNT,
+            pressure_baseline: BaselineTracker::default(),
+            slider_anchor: None,
+            macros: MacroPlayer::start().expect("Error building macro keyboard"),
+            gestures: GestureDetector::default(),
+            layer_shifted: false,
+            shifted_tablet_buttons: 0,
+            media_shifted: false,
+            drag: None,
+            repeating: None,
+            dead_zone: DeadZone::default(),
         }
     }
 
+    /// Switches until the config is reloaded. The file keeps the profile picked in the GUI.
+    fn cycle_profile(&mut self) {
+        let mut config = self.config.write().unwrap();
+        println!("Switched to profile \"{}\"", config.cycle_profile());
+    }
+
+    /// Stretches the configured tablet area over the configured output region.
+    /// The axis range of the virtual pen covers all monitors.
+    fn map_to_area(&self, x: i32, y: i32) -> (i32, i32) {
+        let config = self.config.read().unwrap();
+        let profile = config.active();
+        let (x, y) = if profile.left_handed {
+            (Self::MAX_X - x, Self::MAX_Y - y)
+        } else {
+            (x, y)
+        };
+        let area = profile.area;
+        let output = profile.output;
+        let fx = ((x - area.x_min) as f32 / (area.x_max - area.x_min).max(1) as f32).clamp(0.0, 1.0);
+        let fy = ((y - area.y_min) as f32 / (area.y_max - area.y_min).max(1) as f32).clamp(0.0, 1.0);
+        (
+            ((output.x + fx * output.width) * Self::MAX_X as f32).round() as i32,
+            ((output.y + fy * output.height) * Self::MAX_Y as f32).round() as i32,
+        )
+    }
+
     fn smooth_coordinates(&mut self, x: i32, y: i32) -> (i32, i32) {
         let target_x = x as f32;
         let target_y = y as f32;
@@ -187,12 +207,13 @@ impl DeviceDispatcher {
         let dx = target_x - self.last_x;
         let dy = target_y - self.last_y;
         let dist_sq = dx * dx + dy * dy;
+        let smoothing = self.config.read().unwrap().active().smoothing.clamp(0.0, 1.0);
         let alpha = if dist_sq > 5000.0 {
-            0.95
+            1.0 - 0.05 * smoothing
         } else if dist_sq > 1000.0 {
-            0.6
+            1.0 - 0.4 * smoothing
         } else {
-            0.3
+            1.0 - 0.7 * smoothing
         };
 
         self.last_x = self.last_x + (target_x - self.last_x) * alpha;
@@ -201,6 +222,12 @@ impl DeviceDispatcher {
         (self.last_x as i32, self.last_y as i32)
     }
 
+    /// Keeps a still pen at one position, in tablet units, before smoothing.
+    fn suppress_jitter(&mut self, x: i32, y: i32) -> (i32, i32) {
+        let radius = self.config.read().unwrap().active().dead_zone;
+        self.dead_zone.filter((x, y), radius, Instant::now())
+    }
+
     pub fn syn(&mut self) -> Result<(), Error> {
         self.virtual_keyboard.emit(&[InputEvent::new(
             EventType::SYNCHRONIZATION,
@@ -215,6 +242,14 @@ impl DeviceDispatcher {
         Ok(())
     }
 
+    /// Runs what is due without a new report: gestures decided by time and
+    /// key repeats. Called between reports, as the tablet stays quiet while
+    /// nothing changes.
+    pub fn tick(&mut self) {
+        self.settle_gestures();
+        self.repeat_keys();
+    }
+
     pub fn dispatch(&mut self, raw_data: &RawDataReader) {
         self.emit_pen_events(raw_data);
         self.emit_tablet_events(raw_data);
@@ -237,37 +272,120 @@ impl DeviceDispatcher {
             .build()
     }
 
+    /// Wheel events for the strip slider and pen gestures.
+    fn virtual_mouse_builder() -> Result<VirtualDevice, Error> {
+        let mut key_set = AttributeSet::<Key>::new();
+        for key in actions::mouse_keys() {
+            key_set.insert(key);
+        }
+        let mut axes = AttributeSet::<RelativeAxisType>::new();
+        // Without X and Y, libinput doesn't treat the device as a pointer.
+        for axis in [
+            RelativeAxisType::REL_X,
+            RelativeAxisType::REL_Y,
+            RelativeAxisType::REL_WHEEL,
+            RelativeAxisType::REL_HWHEEL,
+            RelativeAxisType::REL_WHEEL_HI_RES,
+            RelativeAxisType::REL_HWHEEL_HI_RES,
+        ] {
+            axes.insert(axis);
+        }
+        VirtualDeviceBuilder::new()?
+            .name("virtual_tablet_mouse")
+            .with_keys(&key_set)?
+            .with_relative_axes(&axes)?
+            .build()
+    }
+
+    /// Scrolls by whole wheel notches; positive is up and right.
+    fn scroll(&mut self, vertical: i32, horizontal: i32) {
+        let mut events = Vec::new();
+        if vertical != 0 {
+            events.push(InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_WHEEL.0, vertical));
+            events.push(InputEvent::new(
+                EventType::RELATIVE,
+                RelativeAxisType::REL_WHEEL_HI_RES.0,
+                vertical * 120,
+            ));
+        }
+        if horizontal != 0 {
+            events.push(InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_HWHEEL.0, horizontal));
+            events.push(InputEvent::new(
+                EventType::RELATIVE,
+                RelativeAxisType::REL_HWHEEL_HI_RES.0,
+                horizontal * 120,
+            ));
+        }
+        self.virtual_mouse.emit(&events).expect("Error emitting scroll.");
+    }
+
+    fn tap_media_key(&mut self, key: Key) {
+        for state in [Self::PRESSED, Self::RELEASED] {
+            self.media_keyboard
+                .emit(&[InputEvent::new(EventType::KEY, key.code(), state)])
+                .expect("Error emitting media keys.");
+        }
+    }
+
+    /// The layer shift button only changes which actions the others run.
     fn binary_flags_to_tablet_key_events(&mut self, raw_button_as_flags: u16) {
-        (0..14)
-            .filter(|i| ![10, 11].contains(i))
-            .for_each(|i| self.emit_tablet_key_event(i, raw_button_as_flags));
+        let shift_button = self.config.read().unwrap().layer_shift_button;
+        self.layer_shifted = shift_button.is_some_and(|id| raw_button_as_flags & (1 << id) == 0);
+        TABLET_BUTTON_IDS
+            .iter()
+            .filter(|&&i| Some(i) != shift_button)
+            .for_each(|&i| self.emit_tablet_key_event(i, raw_button_as_flags));
+    }
+
+    /// Whether tablet button `i` was last pressed while the layer was shifted.
+    /// Its action is released in the layer it was pressed in.
+    fn pressed_shifted(&self, i: u8) -> bool {
+        self.shifted_tablet_buttons & (1 << i) != 0
     }
 
     pub fn emit_tablet_key_event(&mut self, i: u8, raw_button_as_flags: u16) {
         let id_as_binary_mask = 1 << i;
         let is_pressed = (raw_button_as_flags & id_as_binary_mask) == 0;
         let was_pressed = (self.tablet_last_raw_pressed_buttons & id_as_binary_mask) == 0;
-        if let Some(state) = match (was_pressed, is_pressed) {
+        let Some(state) = (match (was_pressed, is_pressed) {
             (false, true) => Some(Self::PRESSED),
             (true, false) => Some(Self::RELEASED),
-            (true, true) => Some(Self::HOLD),
             _ => None,
-        } {
-            if let Some(keys) = self.tablet_button_id_to_key_code_map.get(&i) {
-                for &key in keys {
-                    self.virtual_keyboard
-                        .emit(&[InputEvent::new(EventType::KEY, key.code(), state)])
-                        .expect("Error emitting virtual keyboard key.");
-                }
-                self.virtual_keyboard
-                    .emit(&[InputEvent::new(
-                        EventType::SYNCHRONIZATION,
-                        Synchronization::SYN_REPORT.0,
-                        0,
-                    )])
-                    .expect("Error emitting SYN.");
+        }) else {
+            return;
+        };
+
+        if self.config.read().unwrap().profile_cycle_button == Some(i) {
+            if state == Self::PRESSED {
+                self.cycle_profile();
             }
+            return;
+        }
+
+        if state == Self::PRESSED {
+            if self.layer_shifted {
+                self.shifted_tablet_buttons |= id_as_binary_mask;
+            } else {
+                self.shifted_tablet_buttons &= !id_as_binary_mask;
+            }
+        }
+
+        let bindings = self.gesture_bindings(i);
+        if bindings.any() {
+            let now = Instant::now();
+            let events = match state {
+                Self::PRESSED => self.gestures.press(i, bindings, now),
+                Self::RELEASED => self.gestures.release(i, bindings, now),
+                _ => Vec::new(),
+            };
+            for (gesture, pressed) in events {
+                self.run_gesture(i, gesture, pressed);
+            }
+            return;
         }
+
+        let action = self.action_for(ButtonSource::Tablet, i, self.pressed_shifted(i));
+        self.run_action(ButtonSource::Tablet, &action, state);
     }
 
     fn virtual_pen_builder(pen_emitted_keys: &[Key]) -> Result<VirtualDevice, Error> {
@@ -301,33 +419,84 @@ impl DeviceDispatcher {
 
     fn emit_pen_events(&mut self, raw_data: &RawDataReader) {
         let y_raw = raw_data.y_axis();
-        let is_multimedia_area = y_raw < 0;
+        let (x_calibrated, y_calibrated, is_multimedia_area) = {
+            let config = self.config.read().unwrap();
+            let calibration = &config.calibration;
+            (
+                calibration.normalize_x(raw_data.x_axis()),
+                calibration.normalize_y(y_raw),
+                calibration.is_media_strip(y_raw),
+            )
+        };
 
-        let raw_pen_buttons = raw_data.pen_buttons();
-        self.raw_pen_buttons_to_pen_key_events(raw_pen_buttons);
-        self.pen_last_raw_pressed_button = raw_pen_buttons;
+        self.raw_pen_buttons_to_pen_key_events(raw_data.pen_buttons());
         let normalized_pressure = self.normalize_pressure(raw_data.pressure());
-        let (smoothed_x, smoothed_y) = self.smooth_coordinates(raw_data.x_axis(), y_raw);
+        let (x, y) = if is_multimedia_area {
+            (x_calibrated, y_raw)
+        } else {
+            let (x, y) = self.suppress_jitter(x_calibrated, y_calibrated);
+            self.map_to_area(x, y)
+        };
+        let (smoothed_x, smoothed_y) = self.smooth_coordinates(x, y);
+
+        // Scrolling and zooming keep the cursor and the tip where they were.
+        if !self.drag_motion(x_calibrated, y_calibrated) {
+            self.raw_pen_abs_to_pen_abs_events(
+                smoothed_x,
+                smoothed_y,
+                normalized_pressure,
+                is_multimedia_area,
+            );
+            self.pen_emit_touch(smoothed_x, is_multimedia_area, normalized_pressure);
+        }
 
-        self.raw_pen_abs_to_pen_abs_events(
-            smoothed_x,
-            smoothed_y,
-            normalized_pressure,
-            is_multimedia_area,
-        );
-        self.pen_emit_touch(smoothed_x, is_multimedia_area, normalized_pressure);
+        if self.control.has_clients() {
+            self.control.broadcast(&DriverEvent::PenSample(PenSample {
+                raw_x: raw_data.x_axis(),
+                raw_y: y_raw,
+                raw_pressure: raw_data.pressure(),
+                x: smoothed_x,
+                y: smoothed_y,
+                pressure: normalized_pressure,
+                media_strip: is_multimedia_area,
+            }));
+        }
     }
 
-    fn normalize_pressure(&self, raw_pressure: i32) -> i32 {
-        let val = Self::RAW_PRESSURE_POINTS - raw_pressure;
+    /// Current correction of the pen's idle pressure, in raw units.
+    pub fn pressure_drift(&self) -> i32 {
+        self.pressure_baseline.offset()
+    }
 
+    /// Pressure for the virtual pen, 0 while the tip is up.
+    fn normalize_pressure(&mut self, raw_pressure: i32) -> i32 {
         let config = self.config.read().unwrap();
+        let profile = config.active();
+        let calibration = &config.calibration;
+        let threshold = profile.threshold(calibration);
+        let corrected = self.pressure_baseline.correct(
+            raw_pressure,
+            calibration,
+            threshold,
+            &config.pressure_drift,
+            Instant::now(),
+        );
 
-        if val <= config.pressure_threshold as i32 {
-            0
-        } else {
-            (val as f32 * config.sensitivity) as i32
+        let drop = calibration.pressure_idle - corrected;
+        let in_contact = match self.contact_since {
+            Some(_) => drop > threshold as i32 - config.tip.release_margin as i32,
+            None => drop > threshold as i32,
+        };
+        if !in_contact {
+            self.contact_since = None;
+            return 0;
         }
+        let since = *self.contact_since.get_or_insert_with(Instant::now);
+        if since.elapsed() < Duration::from_millis(config.tip.min_contact_ms) {
+            return 0;
+        }
+        // Between the release and press thresholds the curve gives 0, but the tip is still down.
+        profile.map_pressure(calibration, corrected).max(1)
     }
 
     fn raw_pen_abs_to_pen_abs_events(
@@ -340,20 +509,25 @@ impl DeviceDispatcher {
         if is_multimedia_area {
             return;
         }
-        self.virtual_pen
-            .emit(&[InputEvent::new(
-                EventType::ABSOLUTE,
-                AbsoluteAxisType::ABS_X.0,
-                x,
-            )])
-            .expect("Error emitting ABS_X.");
-        self.virtual_pen
-            .emit(&[InputEvent::new(
-                EventType::ABSOLUTE,
-                AbsoluteAxisType::ABS_Y.0,
-                y.clamp(0, Self::MAX_Y),
-            )])
-            .expect("Error emitting ABS_Y.");
+        let pen_mode = self.config.read().unwrap().active().pen_mode;
+        // In touch only mode a hovering pen leaves the cursor where it was.
+        if pen_mode != PenMode::TouchOnly || pressure > 0 {
+            self.virtual_pen
+                .emit(&[InputEvent::new(
+                    EventType::ABSOLUTE,
+                    AbsoluteAxisType::ABS_X.0,
+                    x,
+                )])
+                .expect("Error emitting ABS_X.");
+            self.virtual_pen
+                .emit(&[InputEvent::new(
+                    EventType::ABSOLUTE,
+                    AbsoluteAxisType::ABS_Y.0,
+                    y.clamp(0, Self::MAX_Y),
+                )])
+                .expect("Error emitting ABS_Y.");
+        }
+        let pressure = if pen_mode == PenMode::TipClickDisabled { 0 } else { pressure };
         self.virtual_pen
             .emit(&[InputEvent::new(
                 EventType::ABSOLUTE,
@@ -371,63 +545,129 @@ impl DeviceDispatcher {
     }
 
     fn pen_emit_touch(&mut self, x: i32, is_multimedia_area: bool, normalized_pressure: i32) {
-        let is_touching = normalized_pressure > 0;
+        let (slider, pen_mode) = {
+            let config = self.config.read().unwrap();
+            let profile = config.active();
+            (profile.media_mode == MediaStripMode::Slider, profile.pen_mode)
+        };
+        // With tip clicks disabled the tip only ever touches the media strip.
+        let is_touching = normalized_pressure > 0
+            && (is_multimedia_area || pen_mode != PenMode::TipClickDisabled);
+        if !is_multimedia_area {
+            self.slider_anchor = None;
+        } else {
+            // The pen may have slid onto the strip while drawing.
+            self.release_touch();
+            if slider {
+                self.media_slider_events(x, is_touching);
+                self.was_touching = is_touching;
+                return;
+            }
+        }
         if let Some(state) = match (self.was_touching, is_touching) {
             (false, true) => Some(Self::PRESSED),
             (true, false) => Some(Self::RELEASED),
             _ => None,
         } {
             if is_multimedia_area {
-                if state == Self::PRESSED {
-                    self.last_pressed_media_button = (x / self.media_button_width) as u8
-                }
-                if let Some(keys) = self
-                    .media_button_id_to_key_code_map
-                    .get(&self.last_pressed_media_button)
-                {
-                    for key in keys {
-                        self.media_keyboard
-                            .emit(&[InputEvent::new(EventType::KEY, key.code(), state)])
-                            .expect("Error emitting media keys.")
-                    }
+                let id = if state == Self::PRESSED {
+                    self.last_pressed_media_button =
+                        self.config.read().unwrap().calibration.media_zone_at(x);
+                    self.media_shifted = self.layer_shifted;
+                    self.last_pressed_media_button
+                } else {
+                    self.last_pressed_media_button.take()
+                };
+                if let Some(id) = id {
+                    let action = self.action_for(ButtonSource::Media, id, self.media_shifted);
+                    self.run_action(ButtonSource::Media, &action, state);
                 }
             } else {
-                if let Some(keys) = self
-                    .media_button_id_to_key_code_map
-                    .get(&self.last_pressed_media_button)
-                {
-                    for key in keys {
-                        self.media_keyboard
-                            .emit(&[InputEvent::new(EventType::KEY, key.code(), Self::RELEASED)])
-                            .expect("Error emitting media keys.")
-                    }
+                // The pen slid off the strip while pressing a media button.
+                if let Some(id) = self.last_pressed_media_button.take() {
+                    let action = self.action_for(ButtonSource::Media, id, self.media_shifted);
+                    self.run_action(ButtonSource::Media, &action, Self::RELEASED);
+                }
+                if state == Self::PRESSED {
+                    self.virtual_pen
+                        .emit(&[InputEvent::new(
+                            EventType::KEY,
+                            Key::BTN_TOUCH.code(),
+                            state,
+                        )])
+                        .expect("Error emitting Touch");
+                    self.touch_down = true;
+                } else {
+                    self.release_touch();
                 }
-                self.virtual_pen
-                    .emit(&[InputEvent::new(
-                        EventType::KEY,
-                        Key::BTN_TOUCH.code(),
-                        state,
-                    )])
-                    .expect("Error emitting Touch");
             }
         }
         self.was_touching = is_touching;
     }
 
-    fn raw_pen_buttons_to_pen_key_events(&mut self, pen_button: u8) {
-        if let Some((state, id)) = match (self.pen_last_raw_pressed_button, pen_button) {
-            (2, x) if x == 6 || x == 4 => Some((Self::PRESSED, x)),
-            (x, 2) if x == 6 || x == 4 => Some((Self::RELEASED, x)),
-            (x, y) if x != 2 && x == y => Some((Self::HOLD, x)),
-            _ => None,
-        } {
-            if let Some(keys) = self.pen_button_id_to_key_code_map.get(&id) {
-                for key in keys {
-                    self.virtual_pen
-                        .emit(&[InputEvent::new(EventType::KEY, key.code(), state)])
-                        .expect("Error emitting pen keys.")
+    /// Lifts the tip of the virtual pen if it is down. Pressure isn't sent
+    /// while the pen is on the media strip, so it is reset here as well.
+    fn release_touch(&mut self) {
+        if !self.touch_down {
+            return;
+        }
+        self.touch_down = false;
+        self.virtual_pen
+            .emit(&[
+                InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_PRESSURE.0, 0),
+                InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), Self::RELEASED),
+            ])
+            .expect("Error emitting Touch");
+    }
+
+    /// Emits one slider step for every `step` the pen moved along the strip.
+    fn media_slider_events(&mut self, x: i32, is_touching: bool) {
+        if !is_touching {
+            self.slider_anchor = None;
+            return;
+        }
+        let Some(anchor) = self.slider_anchor else {
+            self.slider_anchor = Some(x);
+            return;
+        };
+        let slider = self.config.read().unwrap().active().media_slider;
+        let step = slider.step.max(1);
+        let steps = (x - anchor) / step;
+        if steps == 0 {
+            return;
+        }
+        self.slider_anchor = Some(anchor + steps * step);
+
+        let up = steps > 0;
+        for _ in 0..steps.abs() {
+            match slider.action {
+                SliderAction::Volume => {
+                    self.tap_media_key(if up { Key::KEY_VOLUMEUP } else { Key::KEY_VOLUMEDOWN })
+                }
+                SliderAction::Scroll => self.scroll(steps.signum(), 0),
+                SliderAction::Zoom => {
+                    self.media_keyboard
+                        .emit(&[InputEvent::new(EventType::KEY, Key::KEY_LEFTCTRL.code(), Self::PRESSED)])
+                        .expect("Error emitting media keys.");
+                    self.scroll(steps.signum(), 0);
+                    self.media_keyboard
+                        .emit(&[InputEvent::new(EventType::KEY, Key::KEY_LEFTCTRL.code(), Self::RELEASED)])
+                        .expect("Error emitting media keys.");
+                }
+                SliderAction::BrushSize => {
+                    self.tap_media_key(if up { Key::KEY_RIGHTBRACE } else { Key::KEY_LEFTBRACE })
                 }
             }
         }
     }
+
+    fn raw_pen_buttons_to_pen_key_events(&mut self, raw_pen_buttons: u8) {
+        let down = pen_buttons::decode(raw_pen_buttons);
+        for (id, pressed) in pen_buttons::edges(self.pen_buttons_down, down) {
+            let state = if pressed { Self::PRESSED } else { Self::RELEASED };
+            let action = self.action_for(ButtonSource::Pen, id, false);
+            self.run_action(ButtonSource::Pen, &action, state);
+        }
+        self.pen_buttons_down = down;
+    }
 }
@@ -0,0 +1,281 @@
+use std::ops::RangeInclusive;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::thread;
+
+use evdev::{EventType, InputEvent, Key};
+
+use super::macros::Macro;
+use super::DeviceDispatcher;
+use crate::config::{Action, MediaStripMode, Mode, MouseButton, PenMode, ScrollDirection};
+
+/// Which group a button belongs to, which decides the device its keys come from.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ButtonSource {
+    Tablet,
+    Pen,
+    Media,
+}
+
+/// Device a key of a button action goes out from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyTarget {
+    Keyboard,
+    MediaKeyboard,
+    Mouse,
+    Pen,
+}
+
+/// Highest key code, `KEY_MAX` in linux/input-event-codes.h.
+const KEY_MAX: u16 = 0x2ff;
+
+/// Codes of the `BTN_*` blocks, which sit between the `KEY_*` codes.
+const BUTTON_CODES: [RangeInclusive<u16>; 3] = [
+    Key::BTN_0.code()..=Key::BTN_GEAR_UP.code(),
+    Key::BTN_DPAD_UP.code()..=Key::BTN_DPAD_RIGHT.code(),
+    Key::BTN_TRIGGER_HAPPY1.code()..=Key::BTN_TRIGGER_HAPPY40.code(),
+];
+
+/// Buttons of the virtual mouse, `BTN_LEFT` to `BTN_TASK`.
+const MOUSE_BUTTONS: RangeInclusive<u16> = Key::BTN_LEFT.code()..=Key::BTN_TASK.code();
+
+pub fn is_button(key: Key) -> bool {
+    BUTTON_CODES.iter().any(|codes| codes.contains(&key.code()))
+}
+
+/// Every `KEY_*` code. Button maps can be edited at runtime, so the keyboards
+/// expose all of them up front.
+pub fn keyboard_keys() -> Vec<Key> {
+    (1..=KEY_MAX).map(Key::new).filter(|&key| !is_button(key)).collect()
+}
+
+pub fn mouse_keys() -> Vec<Key> {
+    MOUSE_BUTTONS.map(Key::new).collect()
+}
+
+/// Pen buttons send the buttons the pen has from the pen, so they click
+/// at the pen's position. Other buttons go out from the mouse.
+pub fn key_target(source: ButtonSource, key: Key) -> KeyTarget {
+    match (is_button(key), source) {
+        (true, ButtonSource::Pen) if DeviceDispatcher::PEN_KEYS.contains(&key) => KeyTarget::Pen,
+        (true, _) => KeyTarget::Mouse,
+        (false, ButtonSource::Media) => KeyTarget::MediaKeyboard,
+        (false, _) => KeyTarget::Keyboard,
+    }
+}
+
+fn mouse_key(button: MouseButton) -> Key {
+    match button {
+        MouseButton::Left => Key::BTN_LEFT,
+        MouseButton::Middle => Key::BTN_MIDDLE,
+        MouseButton::Right => Key::BTN_RIGHT,
+    }
+}
+
+fn keys_from_names(names: &[String]) -> Vec<Key> {
+    names.iter().filter_map(|name| Key::from_str(name).ok()).collect()
+}
+
+/// Runs `sh -c command` in its own process group, so it outlives the driver
+/// and doesn't get the driver's Ctrl+C.
+fn run_command(command: &str) {
+    let spawned = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn();
+    match spawned {
+        // Reaped in the background so it doesn't linger as a zombie.
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => eprintln!("Error running \"{}\": {}", command, e),
+    }
+}
+
+impl DeviceDispatcher {
+    /// Action of a button in the normal or the shifted layer. Buttons without
+    /// a shifted action keep their normal one.
+    pub(super) fn action_for(&self, source: ButtonSource, id: u8, shifted: bool) -> Action {
+        let config = self.config.read().unwrap();
+        let profile = config.active();
+        let (map, shifted_map) = match source {
+            ButtonSource::Tablet => (&profile.tablet_buttons, Some(&profile.shifted_tablet_buttons)),
+            ButtonSource::Pen => (&profile.pen_buttons, None),
+            ButtonSource::Media => (&profile.media_buttons, Some(&profile.shifted_media_buttons)),
+        };
+        shifted_map
+            .filter(|_| shifted)
+            .and_then(|shifted_map| shifted_map.get(&id))
+            .or_else(|| map.get(&id))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Presses or releases a key chord. Modifiers go down first and come up last.
+    fn hold_keys(&mut self, source: ButtonSource, names: &[String], repeat: Option<(u64, u64)>, state: i32) {
+        let keys = keys_from_names(names);
+        if state == Self::PRESSED {
+            keys.iter().for_each(|&key| self.emit_key(source, key, state));
+            self.start_repeat(source, &keys, repeat);
+        } else {
+            self.stop_repeat(source, &keys);
+            keys.iter().rev().for_each(|&key| self.emit_key(source, key, state));
+        }
+    }
+
+    /// Carries out `action` for a button that was pressed or released.
+    /// Only key and mouse button actions follow the button; the rest fire on press.
+    pub(super) fn run_action(&mut self, source: ButtonSource, action: &Action, state: i32) {
+        match action {
+            Action::None => {}
+            Action::Keys(names) => self.hold_keys(source, names, None, state),
+            Action::RepeatKeys(repeated) => self.hold_keys(
+                source,
+                &repeated.keys,
+                Some((repeated.delay_ms, repeated.interval_ms)),
+                state,
+            ),
+            Action::Mouse(button) => self.emit_key(source, mouse_key(*button), state),
+            Action::Drag(mode) => match state {
+                Self::PRESSED => self.start_drag(*mode),
+                _ => self.end_drag(),
+            },
+            _ if state != Self::PRESSED => {}
+            Action::Scroll(direction) => match direction {
+                ScrollDirection::Up => self.scroll(1, 0),
+                ScrollDirection::Down => self.scroll(-1, 0),
+                ScrollDirection::Left => self.scroll(0, -1),
+                ScrollDirection::Right => self.scroll(0, 1),
+            },
+            Action::Sequence(steps) => self.macros.play(Macro::Sequence(steps.clone())),
+            Action::Text(text) => self.macros.play(Macro::Text(text.clone())),
+            Action::Command(command) => run_command(command),
+            Action::SwitchProfile(name) => self.switch_profile(name),
+            Action::ToggleMode(mode) => self.toggle_mode(*mode),
+        }
+    }
+
+    pub(super) fn emit_key(&mut self, source: ButtonSource, key: Key, state: i32) {
+        let device = match key_target(source, key) {
+            KeyTarget::Pen => &mut self.virtual_pen,
+            KeyTarget::Mouse => &mut self.virtual_mouse,
+            KeyTarget::MediaKeyboard => &mut self.media_keyboard,
+            KeyTarget::Keyboard => &mut self.virtual_keyboard,
+        };
+        device
+            .emit(&[InputEvent::new(EventType::KEY, key.code(), state)])
+            .expect("Error emitting button key.");
+    }
+
+    /// Switches until the config is reloaded, like `cycle_profile`.
+    fn switch_profile(&mut self, name: &str) {
+        let mut config = self.config.write().unwrap();
+        if !config.profiles.iter().any(|profile| profile.name == name) {
+            eprintln!("Cannot switch to unknown profile \"{}\"", name);
+            return;
+        }
+        config.active_profile = name.to_string();
+        println!("Switched to profile \"{}\"", name);
+    }
+
+    /// Flips a setting of the active profile until the config is reloaded.
+    fn toggle_mode(&mut self, mode: Mode) {
+        let mut config = self.config.write().unwrap();
+        let profile = config.active_mut();
+        match mode {
+            Mode::MediaSlider => {
+                profile.media_mode = match profile.media_mode {
+                    MediaStripMode::Buttons => MediaStripMode::Slider,
+                    MediaStripMode::Slider => MediaStripMode::Buttons,
+                };
+                println!("Media strip mode: {:?}", profile.media_mode);
+            }
+            Mode::LeftHanded => {
+                profile.left_handed = !profile.left_handed;
+                println!("Left-handed: {}", profile.left_handed);
+            }
+            Mode::TipClick => {
+                profile.pen_mode = match profile.pen_mode {
+                    PenMode::TipClickDisabled => PenMode::Normal,
+                    _ => PenMode::TipClickDisabled,
+                };
+                println!("Pen mode: {:?}", profile.pen_mode);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Profile;
+
+    fn registered_keys(target: KeyTarget) -> Vec<Key> {
+        match target {
+            KeyTarget::Keyboard | KeyTarget::MediaKeyboard => keyboard_keys(),
+            KeyTarget::Mouse => mouse_keys(),
+            KeyTarget::Pen => DeviceDispatcher::PEN_KEYS.to_vec(),
+        }
+    }
+
+    #[test]
+    fn tells_buttons_from_keys() {
+        assert!(is_button(Key::BTN_LEFT));
+        assert!(is_button(Key::BTN_STYLUS));
+        assert!(is_button(Key::BTN_DPAD_UP));
+        assert!(!is_button(Key::KEY_PLAYER));
+        assert!(!is_button(Key::KEY_ZOOMIN));
+        assert!(!is_button(Key::KEY_BRIGHTNESSUP));
+    }
+
+    #[test]
+    fn keyboards_have_keys_above_the_buttons() {
+        let keys = keyboard_keys();
+        // 0x2b0 is KEY_MACRO1, which evdev has no name for.
+        for key in [Key::KEY_PLAYER, Key::KEY_ZOOMIN, Key::KEY_BRIGHTNESS_MAX, Key::new(0x2b0)] {
+            assert!(keys.contains(&key), "{:?} missing", key);
+        }
+        assert!(keys.iter().all(|&key| !is_button(key)));
+    }
+
+    #[test]
+    fn default_actions_go_to_devices_that_have_their_keys() {
+        let profile = Profile::default();
+        let maps = [
+            (ButtonSource::Tablet, &profile.tablet_buttons),
+            (ButtonSource::Tablet, &profile.tablet_hold),
+            (ButtonSource::Tablet, &profile.tablet_double_tap),
+            (ButtonSource::Tablet, &profile.shifted_tablet_buttons),
+            (ButtonSource::Pen, &profile.pen_buttons),
+            (ButtonSource::Media, &profile.media_buttons),
+            (ButtonSource::Media, &profile.shifted_media_buttons),
+        ];
+        for (source, map) in maps {
+            for action in map.values() {
+                let keys = match action {
+                    Action::Keys(names) => {
+                        let keys = keys_from_names(names);
+                        assert_eq!(keys.len(), names.len(), "unknown key in {:?}", names);
+                        keys
+                    }
+                    Action::Mouse(button) => vec![mouse_key(*button)],
+                    _ => continue,
+                };
+                for key in keys {
+                    let target = key_target(source, key);
+                    assert!(
+                        registered_keys(target).contains(&key),
+                        "{:?} is sent to {:?}, which doesn't have it",
+                        key,
+                        target
+                    );
+                }
+            }
+        }
+    }
+}
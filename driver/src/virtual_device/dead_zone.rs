@@ -0,0 +1,113 @@
+use std::time::{Duration, Instant};
+
+/// How long the pen has to stay within the radius to count as still again.
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Holds the position of a still pen so its jitter doesn't move the cursor.
+/// Positions are passed through once the pen leaves the radius, and held
+/// again after it has stayed within the radius for `SETTLE_TIME`.
+#[derive(Default)]
+pub struct DeadZone {
+    /// Position reported while the pen is still.
+    anchor: Option<(i32, i32)>,
+    /// Where the moving pen may be coming to rest, and since when.
+    candidate: Option<((i32, i32), Instant)>,
+}
+
+fn within(a: (i32, i32), b: (i32, i32), radius: i32) -> bool {
+    let (dx, dy) = ((a.0 - b.0) as i64, (a.1 - b.1) as i64);
+    dx * dx + dy * dy <= (radius as i64) * (radius as i64)
+}
+
+impl DeadZone {
+    pub fn filter(&mut self, position: (i32, i32), radius: i32, now: Instant) -> (i32, i32) {
+        if radius <= 0 {
+            self.anchor = None;
+            self.candidate = None;
+            return position;
+        }
+
+        if let Some(anchor) = self.anchor {
+            if within(position, anchor, radius) {
+                return anchor;
+            }
+            self.anchor = None;
+        }
+
+        match self.candidate {
+            Some((point, since)) if within(position, point, radius) => {
+                if now - since >= SETTLE_TIME {
+                    self.anchor = Some(point);
+                    self.candidate = None;
+                    return point;
+                }
+            }
+            _ => self.candidate = Some((position, now)),
+        }
+        position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: i32 = 10;
+
+    #[test]
+    fn jitter_inside_the_radius_stays_at_the_anchor() {
+        let mut dead_zone = DeadZone::default();
+        let start = Instant::now();
+        dead_zone.filter((100, 100), RADIUS, start);
+        let settled = start + SETTLE_TIME;
+        assert_eq!(dead_zone.filter((100, 100), RADIUS, settled), (100, 100));
+
+        for (i, jitter) in [(3, -2), (-6, 4), (0, 9), (-7, -7)].into_iter().enumerate() {
+            let now = settled + Duration::from_millis(5 * (i as u64 + 1));
+            let position = (100 + jitter.0, 100 + jitter.1);
+            assert_eq!(dead_zone.filter(position, RADIUS, now), (100, 100));
+        }
+    }
+
+    #[test]
+    fn move_past_the_radius_follows_the_pen_immediately() {
+        let mut dead_zone = DeadZone::default();
+        let start = Instant::now();
+        dead_zone.filter((100, 100), RADIUS, start);
+        dead_zone.filter((100, 100), RADIUS, start + SETTLE_TIME);
+
+        let now = start + SETTLE_TIME + Duration::from_millis(5);
+        assert_eq!(dead_zone.filter((100, 111), RADIUS, now), (100, 111));
+        let later = now + Duration::from_millis(5);
+        assert_eq!(dead_zone.filter((100, 113), RADIUS, later), (100, 113));
+    }
+
+    #[test]
+    fn slow_creep_reanchors_after_settle_time() {
+        let mut dead_zone = DeadZone::default();
+        let start = Instant::now();
+        dead_zone.filter((100, 100), RADIUS, start);
+        dead_zone.filter((100, 100), RADIUS, start + SETTLE_TIME);
+
+        // Leaving the radius slowly is a move like any other.
+        let left = start + SETTLE_TIME * 2;
+        assert_eq!(dead_zone.filter((111, 100), RADIUS, left), (111, 100));
+        // Resting near the new spot passes positions through until it has settled.
+        let resting = left + SETTLE_TIME / 2;
+        assert_eq!(dead_zone.filter((113, 100), RADIUS, resting), (113, 100));
+        let settled = left + SETTLE_TIME;
+        assert_eq!(dead_zone.filter((114, 100), RADIUS, settled), (111, 100));
+        let after = settled + Duration::from_millis(5);
+        assert_eq!(dead_zone.filter((116, 102), RADIUS, after), (111, 100));
+    }
+
+    #[test]
+    fn zero_radius_passes_positions_through() {
+        let mut dead_zone = DeadZone::default();
+        let start = Instant::now();
+        dead_zone.filter((100, 100), RADIUS, start);
+        dead_zone.filter((100, 100), RADIUS, start + SETTLE_TIME);
+        let now = start + SETTLE_TIME * 2;
+        assert_eq!(dead_zone.filter((101, 100), 0, now), (101, 100));
+    }
+}
@@ -0,0 +1,169 @@
+use evdev::{EventType, InputEvent, Key, RelativeAxisType};
+
+use super::DeviceDispatcher;
+use crate::config::DragMode;
+
+/// Hi-res wheel units in one notch.
+const NOTCH: i32 = 120;
+
+/// A pen drag in progress, started by a button bound to `Action::Drag`.
+pub struct PenDrag {
+    mode: DragMode,
+    /// Pen position at the previous report, in tablet units.
+    last: Option<(i32, i32)>,
+    /// Vertical and horizontal wheel.
+    wheels: [Wheel; 2],
+}
+
+impl PenDrag {
+    fn new(mode: DragMode) -> Self {
+        Self {
+            mode,
+            last: None,
+            wheels: Default::default(),
+        }
+    }
+}
+
+/// Splits wheel movement into hi-res units and whole notches, carrying what
+/// doesn't add up to one over to the next movement.
+#[derive(Default)]
+struct Wheel {
+    /// Hi-res wheel movement not sent yet.
+    pending: f32,
+    /// Hi-res units sent since the last whole notch.
+    partial_notch: i32,
+}
+
+impl Wheel {
+    /// Adds `movement` in hi-res units. Returns the hi-res units and the
+    /// whole notches to send, which are 0 while they haven't added up to one.
+    fn turn(&mut self, movement: f32) -> (i32, i32) {
+        self.pending += movement;
+        let hi_res = self.pending.trunc() as i32;
+        self.pending -= hi_res as f32;
+        self.partial_notch += hi_res;
+        let notches = self.partial_notch / NOTCH;
+        self.partial_notch -= notches * NOTCH;
+        (hi_res, notches)
+    }
+}
+
+impl DeviceDispatcher {
+    pub(super) fn start_drag(&mut self, mode: DragMode) {
+        self.end_drag();
+        match mode {
+            DragMode::Scroll => {}
+            DragMode::Pan => self.emit_drag_key(true, Key::BTN_MIDDLE, Self::PRESSED),
+            DragMode::Zoom => self.emit_drag_key(false, Key::KEY_LEFTCTRL, Self::PRESSED),
+        }
+        self.drag = Some(PenDrag::new(mode));
+    }
+
+    pub(super) fn end_drag(&mut self) {
+        match self.drag.take().map(|drag| drag.mode) {
+            Some(DragMode::Pan) => self.emit_drag_key(true, Key::BTN_MIDDLE, Self::RELEASED),
+            Some(DragMode::Zoom) => self.emit_drag_key(false, Key::KEY_LEFTCTRL, Self::RELEASED),
+            _ => {}
+        }
+    }
+
+    fn emit_drag_key(&mut self, pen: bool, key: Key, state: i32) {
+        let device = if pen { &mut self.virtual_pen } else { &mut self.virtual_keyboard };
+        device
+            .emit(&[InputEvent::new(EventType::KEY, key.code(), state)])
+            .expect("Error emitting drag key.");
+    }
+
+    /// Turns pen movement into wheel events while scrolling or zooming.
+    /// Returns whether the cursor should stay where it is.
+    pub(super) fn drag_motion(&mut self, x: i32, y: i32) -> bool {
+        let settings = self.config.read().unwrap().active().drag;
+        let Some(drag) = &mut self.drag else {
+            return false;
+        };
+        if drag.mode == DragMode::Pan {
+            return false;
+        }
+        let Some((last_x, last_y)) = drag.last.replace((x, y)) else {
+            return true;
+        };
+
+        let per_unit = NOTCH as f32 / settings.step.max(1) as f32;
+        let sign = if settings.invert { -1.0 } else { 1.0 };
+        // Wheel up scrolls the content down, so it follows the pen.
+        let (vertical, horizontal) = match drag.mode {
+            DragMode::Zoom => ((last_y - y) as f32 * per_unit, 0.0),
+            _ => (
+                (y - last_y) as f32 * per_unit * sign,
+                (last_x - x) as f32 * per_unit * sign,
+            ),
+        };
+
+        let mut events = Vec::new();
+        let axes = [
+            (RelativeAxisType::REL_WHEEL, RelativeAxisType::REL_WHEEL_HI_RES, vertical),
+            (RelativeAxisType::REL_HWHEEL, RelativeAxisType::REL_HWHEEL_HI_RES, horizontal),
+        ];
+        for (wheel, (axis, hi_res_axis, movement)) in drag.wheels.iter_mut().zip(axes) {
+            let (hi_res, notches) = wheel.turn(movement);
+            if hi_res != 0 {
+                events.push(InputEvent::new(EventType::RELATIVE, hi_res_axis.0, hi_res));
+            }
+            // Applications without hi-res support still get whole notches.
+            if notches != 0 {
+                events.push(InputEvent::new(EventType::RELATIVE, axis.0, notches));
+            }
+        }
+        if !events.is_empty() {
+            self.virtual_mouse.emit(&events).expect("Error emitting drag scroll.");
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_notches_carry_over() {
+        let mut wheel = Wheel::default();
+        assert_eq!(wheel.turn(50.0), (50, 0));
+        assert_eq!(wheel.turn(50.0), (50, 0));
+        assert_eq!(wheel.turn(50.0), (50, 1));
+        assert_eq!(wheel.turn(NOTCH as f32 * 2.0), (240, 2));
+        assert_eq!(wheel.partial_notch, 30);
+    }
+
+    #[test]
+    fn fractions_of_a_hi_res_unit_carry_over() {
+        let mut wheel = Wheel::default();
+        assert_eq!(wheel.turn(0.4), (0, 0));
+        assert_eq!(wheel.turn(0.4), (0, 0));
+        assert_eq!(wheel.turn(0.4), (1, 0));
+    }
+
+    #[test]
+    fn changing_direction_unwinds_the_partial_notch() {
+        let mut wheel = Wheel::default();
+        assert_eq!(wheel.turn(100.0), (100, 0));
+        // Going back first undoes the 100 units, so no notch either way yet.
+        assert_eq!(wheel.turn(-150.0), (-150, 0));
+        assert_eq!(wheel.turn(-80.0), (-80, -1));
+        assert_eq!(wheel.partial_notch, -10);
+        assert_eq!(wheel.turn(130.0), (130, 1));
+    }
+
+    #[test]
+    fn a_new_drag_starts_without_partial_notches() {
+        let mut drag = PenDrag::new(DragMode::Scroll);
+        drag.wheels[0].turn(110.0);
+        drag.wheels[1].turn(-110.5);
+
+        // Releasing the button ends the drag; the next press starts a new one.
+        let mut drag = PenDrag::new(DragMode::Scroll);
+        assert_eq!(drag.wheels[0].turn(20.0), (20, 0));
+        assert_eq!(drag.wheels[1].turn(-20.0), (-20, 0));
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use super::actions::ButtonSource;
+use super::DeviceDispatcher;
+use crate::config::{ButtonTiming, Profile};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gesture {
+    Tap,
+    Hold,
+    DoubleTap,
+}
+
+/// Which gestures a button has actions for besides its tap.
+#[derive(Clone, Copy, Default)]
+pub struct Bindings {
+    pub hold: bool,
+    pub double_tap: bool,
+}
+
+impl Bindings {
+    pub fn of(profile: &Profile, id: u8) -> Self {
+        Self {
+            hold: profile.tablet_hold.contains_key(&id),
+            double_tap: profile.tablet_double_tap.contains_key(&id),
+        }
+    }
+
+    pub fn any(self) -> bool {
+        self.hold || self.double_tap
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Phase {
+    /// Down, but not long enough to be a hold yet.
+    Pressed(Instant),
+    /// Up after a short press, waiting for a second tap.
+    Released(Instant),
+    /// The gesture is known and its action is down until the button comes up.
+    Active(Gesture),
+}
+
+/// Tells taps, holds and double-taps of the tablet buttons apart. Results are
+/// gestures whose action goes down (`true`) or up (`false`).
+#[derive(Default)]
+pub struct GestureDetector {
+    buttons: BTreeMap<u8, Phase>,
+}
+
+impl GestureDetector {
+    pub fn press(&mut self, id: u8, bindings: Bindings, now: Instant) -> Vec<(Gesture, bool)> {
+        match self.buttons.get(&id) {
+            Some(Phase::Released(_)) if bindings.double_tap => {
+                self.buttons.insert(id, Phase::Active(Gesture::DoubleTap));
+                vec![(Gesture::DoubleTap, true)]
+            }
+            _ => {
+                self.buttons.insert(id, Phase::Pressed(now));
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn release(&mut self, id: u8, bindings: Bindings, now: Instant) -> Vec<(Gesture, bool)> {
+        match self.buttons.get(&id).copied() {
+            // The tap has to wait, the button may be tapped again.
+            Some(Phase::Pressed(_)) if bindings.double_tap => {
+                self.buttons.insert(id, Phase::Released(now));
+                Vec::new()
+            }
+            Some(Phase::Pressed(_)) => {
+                self.buttons.remove(&id);
+                vec![(Gesture::Tap, true), (Gesture::Tap, false)]
+            }
+            Some(Phase::Active(gesture)) => {
+                self.buttons.remove(&id);
+                vec![(gesture, false)]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Settles presses that became holds and taps that weren't followed by a
+    /// second one. A button held past `hold_ms` without a hold action holds its tap.
+    pub fn tick(
+        &mut self,
+        now: Instant,
+        timing: ButtonTiming,
+        bindings: impl Fn(u8) -> Bindings,
+    ) -> Vec<(u8, Gesture, bool)> {
+        let hold = Duration::from_millis(timing.hold_ms);
+        let double_tap = Duration::from_millis(timing.double_tap_ms);
+        let mut events = Vec::new();
+        self.buttons.retain(|&id, phase| match *phase {
+            Phase::Pressed(since) if now - since >= hold => {
+                let gesture = if bindings(id).hold { Gesture::Hold } else { Gesture::Tap };
+                events.push((id, gesture, true));
+                *phase = Phase::Active(gesture);
+                true
+            }
+            Phase::Released(since) if now - since >= double_tap => {
+                events.push((id, Gesture::Tap, true));
+                events.push((id, Gesture::Tap, false));
+                false
+            }
+            _ => true,
+        });
+        events
+    }
+}
+
+impl DeviceDispatcher {
+    pub(super) fn gesture_bindings(&self, id: u8) -> Bindings {
+        Bindings::of(self.config.read().unwrap().active(), id)
+    }
+
+    pub(super) fn run_gesture(&mut self, id: u8, gesture: Gesture, pressed: bool) {
+        let action = match gesture {
+            Gesture::Tap => self.action_for(ButtonSource::Tablet, id, self.pressed_shifted(id)),
+            Gesture::Hold | Gesture::DoubleTap => {
+                let config = self.config.read().unwrap();
+                let profile = config.active();
+                let map = match gesture {
+                    Gesture::Hold => &profile.tablet_hold,
+                    _ => &profile.tablet_double_tap,
+                };
+                map.get(&id).cloned().unwrap_or_default()
+            }
+        };
+        let state = if pressed { Self::PRESSED } else { Self::RELEASED };
+        self.run_action(ButtonSource::Tablet, &action, state);
+    }
+
+    /// Fires gestures that are decided by time alone.
+    pub(super) fn settle_gestures(&mut self) {
+        let events = {
+            let config = self.config.read().unwrap();
+            let profile = config.active();
+            self.gestures.tick(Instant::now(), config.button_timing, |id| Bindings::of(profile, id))
+        };
+        for (id, gesture, pressed) in events {
+            self.run_gesture(id, gesture, pressed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: u8 = 3;
+    const TAP_ONLY: Bindings = Bindings { hold: false, double_tap: false };
+    const HOLD: Bindings = Bindings { hold: true, double_tap: false };
+    const DOUBLE_TAP: Bindings = Bindings { hold: false, double_tap: true };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn tick(detector: &mut GestureDetector, now: Instant, bindings: Bindings) -> Vec<(u8, Gesture, bool)> {
+        detector.tick(now, ButtonTiming::default(), |_| bindings)
+    }
+
+    #[test]
+    fn short_press_is_a_tap() {
+        let timing = ButtonTiming::default();
+        let mut detector = GestureDetector::default();
+        let start = Instant::now();
+        assert!(detector.press(ID, HOLD, start).is_empty());
+        let before_hold = start + ms(timing.hold_ms - 1);
+        assert!(tick(&mut detector, before_hold, HOLD).is_empty());
+        assert_eq!(
+            detector.release(ID, HOLD, before_hold),
+            vec![(Gesture::Tap, true), (Gesture::Tap, false)]
+        );
+        assert!(tick(&mut detector, start + ms(timing.hold_ms), HOLD).is_empty());
+    }
+
+    #[test]
+    fn hold_fires_at_hold_time() {
+        let timing = ButtonTiming::default();
+        let mut detector = GestureDetector::default();
+        let start = Instant::now();
+        detector.press(ID, HOLD, start);
+        assert_eq!(
+            tick(&mut detector, start + ms(timing.hold_ms), HOLD),
+            vec![(ID, Gesture::Hold, true)]
+        );
+        // Already decided, so later ticks don't fire it again.
+        assert!(tick(&mut detector, start + ms(timing.hold_ms * 2), HOLD).is_empty());
+    }
+
+    #[test]
+    fn release_after_hold_does_not_tap() {
+        let timing = ButtonTiming::default();
+        let mut detector = GestureDetector::default();
+        let start = Instant::now();
+        detector.press(ID, HOLD, start);
+        tick(&mut detector, start + ms(timing.hold_ms), HOLD);
+        let release = start + ms(timing.hold_ms + 100);
+        assert_eq!(detector.release(ID, HOLD, release), vec![(Gesture::Hold, false)]);
+        assert!(tick(&mut detector, release + ms(timing.double_tap_ms), HOLD).is_empty());
+    }
+
+    #[test]
+    fn long_press_without_hold_action_holds_the_tap() {
+        let timing = ButtonTiming::default();
+        let mut detector = GestureDetector::default();
+        let start = Instant::now();
+        detector.press(ID, TAP_ONLY, start);
+        assert_eq!(
+            tick(&mut detector, start + ms(timing.hold_ms), TAP_ONLY),
+            vec![(ID, Gesture::Tap, true)]
+        );
+        assert_eq!(
+            detector.release(ID, TAP_ONLY, start + ms(timing.hold_ms + 1)),
+            vec![(Gesture::Tap, false)]
+        );
+    }
+
+    #[test]
+    fn second_tap_inside_the_window_is_a_double_tap() {
+        let timing = ButtonTiming::default();
+        let mut detector = GestureDetector::default();
+        let start = Instant::now();
+        detector.press(ID, DOUBLE_TAP, start);
+        assert!(detector.release(ID, DOUBLE_TAP, start + ms(50)).is_empty());
+        let second = start + ms(50 + timing.double_tap_ms - 1);
+        assert!(tick(&mut detector, second, DOUBLE_TAP).is_empty());
+        assert_eq!(
+            detector.press(ID, DOUBLE_TAP, second),
+            vec![(Gesture::DoubleTap, true)]
+        );
+        assert_eq!(
+            detector.release(ID, DOUBLE_TAP, second + ms(50)),
+            vec![(Gesture::DoubleTap, false)]
+        );
+    }
+
+    #[test]
+    fn taps_outside_the_window_are_two_taps() {
+        let timing = ButtonTiming::default();
+        let mut detector = GestureDetector::default();
+        let start = Instant::now();
+        let taps = vec![(ID, Gesture::Tap, true), (ID, Gesture::Tap, false)];
+
+        detector.press(ID, DOUBLE_TAP, start);
+        let first_up = start + ms(50);
+        detector.release(ID, DOUBLE_TAP, first_up);
+        let expired = first_up + ms(timing.double_tap_ms);
+        assert_eq!(tick(&mut detector, expired, DOUBLE_TAP), taps);
+
+        let second = expired + ms(10);
+        assert!(detector.press(ID, DOUBLE_TAP, second).is_empty());
+        let second_up = second + ms(50);
+        assert!(detector.release(ID, DOUBLE_TAP, second_up).is_empty());
+        assert_eq!(tick(&mut detector, second_up + ms(timing.double_tap_ms), DOUBLE_TAP), taps);
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::Error;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use evdev::uinput::VirtualDevice;
+use evdev::{EventType, InputEvent, Key};
+
+use super::actions::keyboard_keys;
+use super::DeviceDispatcher;
+use crate::config::SequenceStep;
+
+/// Pause between typed keys, so applications don't drop any.
+const KEY_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Key input that takes a while to play.
+pub enum Macro {
+    Sequence(Vec<SequenceStep>),
+    Text(String),
+}
+
+/// Plays macros on a keyboard of its own, so delays don't hold up pen reports.
+pub struct MacroPlayer {
+    sender: Sender<Macro>,
+}
+
+impl MacroPlayer {
+    pub fn start() -> Result<Self, Error> {
+        let mut keyboard = DeviceDispatcher::virtual_keyboard_builder(&keyboard_keys())?;
+        let (sender, macros) = mpsc::channel();
+        thread::spawn(move || {
+            for m in macros {
+                match m {
+                    Macro::Sequence(steps) => play_sequence(&mut keyboard, &steps),
+                    Macro::Text(text) => type_text(&mut keyboard, &text),
+                }
+            }
+        });
+        Ok(Self { sender })
+    }
+
+    pub fn play(&self, m: Macro) {
+        let _ = self.sender.send(m);
+    }
+}
+
+fn tap(keyboard: &mut VirtualDevice, keys: &[Key]) {
+    let presses = keys.iter().map(|key| (key, 1));
+    let releases = keys.iter().rev().map(|key| (key, 0));
+    for (key, state) in presses.chain(releases) {
+        if let Err(e) = keyboard.emit(&[InputEvent::new(EventType::KEY, key.code(), state)]) {
+            eprintln!("Error emitting macro key: {}", e);
+        }
+        thread::sleep(KEY_INTERVAL);
+    }
+}
+
+fn play_sequence(keyboard: &mut VirtualDevice, steps: &[SequenceStep]) {
+    for step in steps {
+        let keys: Vec<Key> = step.keys.iter().filter_map(|name| Key::from_str(name).ok()).collect();
+        tap(keyboard, &keys);
+        thread::sleep(Duration::from_millis(step.delay_ms));
+    }
+}
+
+/// Key and Shift state producing `c` on a US layout.
+fn us_layout_key(c: char) -> Option<(Key, bool)> {
+    let (name, shift) = match c {
+        'a'..='z' | '0'..='9' => return Some((Key::from_str(&format!("KEY_{}", c.to_ascii_uppercase())).ok()?, false)),
+        'A'..='Z' => return Some((Key::from_str(&format!("KEY_{}", c)).ok()?, true)),
+        ' ' => (Key::KEY_SPACE, false),
+        '\n' => (Key::KEY_ENTER, false),
+        '\t' => (Key::KEY_TAB, false),
+        '-' => (Key::KEY_MINUS, false),
+        '=' => (Key::KEY_EQUAL, false),
+        '[' => (Key::KEY_LEFTBRACE, false),
+        ']' => (Key::KEY_RIGHTBRACE, false),
+        '\\' => (Key::KEY_BACKSLASH, false),
+        ';' => (Key::KEY_SEMICOLON, false),
+        '\'' => (Key::KEY_APOSTROPHE, false),
+        '`' => (Key::KEY_GRAVE, false),
+        ',' => (Key::KEY_COMMA, false),
+        '.' => (Key::KEY_DOT, false),
+        '/' => (Key::KEY_SLASH, false),
+        '!' => (Key::KEY_1, true),
+        '@' => (Key::KEY_2, true),
+        '#' => (Key::KEY_3, true),
+        '$' => (Key::KEY_4, true),
+        '%' => (Key::KEY_5, true),
+        '^' => (Key::KEY_6, true),
+        '&' => (Key::KEY_7, true),
+        '*' => (Key::KEY_8, true),
+        '(' => (Key::KEY_9, true),
+        ')' => (Key::KEY_0, true),
+        '_' => (Key::KEY_MINUS, true),
+        '+' => (Key::KEY_EQUAL, true),
+        '{' => (Key::KEY_LEFTBRACE, true),
+        '}' => (Key::KEY_RIGHTBRACE, true),
+        '|' => (Key::KEY_BACKSLASH, true),
+        ':' => (Key::KEY_SEMICOLON, true),
+        '"' => (Key::KEY_APOSTROPHE, true),
+        '~' => (Key::KEY_GRAVE, true),
+        '<' => (Key::KEY_COMMA, true),
+        '>' => (Key::KEY_DOT, true),
+        '?' => (Key::KEY_SLASH, true),
+        _ => return None,
+    };
+    Some((name, shift))
+}
+
+/// Types `text` key by key. Characters missing from the US layout are entered
+/// as Ctrl+Shift+U, their hex code and Space, which GTK and IBus understand.
+fn type_text(keyboard: &mut VirtualDevice, text: &str) {
+    for c in text.chars() {
+        match us_layout_key(c) {
+            Some((key, true)) => tap(keyboard, &[Key::KEY_LEFTSHIFT, key]),
+            Some((key, false)) => tap(keyboard, &[key]),
+            None => {
+                tap(keyboard, &[Key::KEY_LEFTCTRL, Key::KEY_LEFTSHIFT, Key::KEY_U]);
+                for digit in format!("{:x}", c as u32).chars() {
+                    if let Some((key, _)) = us_layout_key(digit) {
+                        tap(keyboard, &[key]);
+                    }
+                }
+                tap(keyboard, &[Key::KEY_SPACE]);
+            }
+        }
+    }
+}
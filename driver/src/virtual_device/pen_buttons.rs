@@ -0,0 +1,142 @@
+use crate::config::PEN_BUTTON_IDS;
+
+/// Bit of the pen button byte that is set while a stylus button is down.
+/// Captured bytes: 2 hovering, 4 lower button, 6 upper button, 0 out of range.
+/// No byte reports both buttons at once.
+const DOWN_BIT: u8 = 0b100;
+/// Bit that tells the buttons apart while `DOWN_BIT` is set: set for the upper
+/// button, clear for the lower one. Without `DOWN_BIT` it only marks a hovering
+/// pen. The other bits don't concern the buttons.
+const UPPER_BIT: u8 = 0b010;
+
+/// Stylus buttons down in a raw pen button byte, one bit per entry of
+/// `PEN_BUTTON_IDS` (lower, then upper). A button's id is the byte it reports.
+/// The tablet reports one button at a time (see `DOWN_BIT`), so at most one
+/// bit is set; `edges` still handles both.
+pub fn decode(raw: u8) -> u8 {
+    let down = raw & DOWN_BIT != 0;
+    let upper = raw & UPPER_BIT != 0;
+    match (down, upper) {
+        (false, _) => 0,
+        (true, false) => 0b01,
+        (true, true) => 0b10,
+    }
+}
+
+/// Buttons that came up (`false`) or went down (`true`) between two decoded
+/// states. Releases come first, so going from one button straight to the
+/// other never has both pressed at once.
+pub fn edges(last: u8, now: u8) -> Vec<(u8, bool)> {
+    let changed = |from: u8, to: u8, pressed: bool| {
+        PEN_BUTTON_IDS
+            .iter()
+            .enumerate()
+            .filter(move |&(i, _)| from & 1 << i != 0 && to & 1 << i == 0)
+            .map(move |(_, &id)| (id, pressed))
+    };
+    changed(last, now, false).chain(changed(now, last, true)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Raw pen button bytes as the tablet reports them.
+    const OUT_OF_RANGE: u8 = 0;
+    const HOVER: u8 = 2;
+    const LOWER: u8 = 4;
+    const UPPER: u8 = 6;
+
+    fn transition(from: u8, to: u8) -> Vec<(u8, bool)> {
+        edges(decode(from), decode(to))
+    }
+
+    #[test]
+    fn decodes_each_byte() {
+        assert_eq!(decode(OUT_OF_RANGE), 0);
+        assert_eq!(decode(HOVER), 0);
+        assert_eq!(decode(LOWER), 0b01);
+        assert_eq!(decode(UPPER), 0b10);
+    }
+
+    #[test]
+    fn ignores_bits_outside_the_buttons() {
+        for other in [0x01, 0x08, 0x21, 0xf0] {
+            assert_eq!(decode(HOVER | other), decode(HOVER));
+            assert_eq!(decode(LOWER | other), decode(LOWER));
+            assert_eq!(decode(UPPER | other), decode(UPPER));
+        }
+    }
+
+    #[test]
+    fn press_and_release_each_button() {
+        assert_eq!(transition(HOVER, LOWER), vec![(LOWER, true)]);
+        assert_eq!(transition(LOWER, HOVER), vec![(LOWER, false)]);
+        assert_eq!(transition(HOVER, UPPER), vec![(UPPER, true)]);
+        assert_eq!(transition(UPPER, HOVER), vec![(UPPER, false)]);
+    }
+
+    #[test]
+    fn holding_a_button_has_no_edges() {
+        for raw in [HOVER, LOWER, UPPER] {
+            assert!(transition(raw, raw).is_empty());
+        }
+    }
+
+    #[test]
+    fn switching_buttons_releases_the_first() {
+        assert_eq!(transition(LOWER, UPPER), vec![(LOWER, false), (UPPER, true)]);
+        assert_eq!(transition(UPPER, LOWER), vec![(UPPER, false), (LOWER, true)]);
+    }
+
+    #[test]
+    fn lower_then_upper_then_hover_then_out_of_range() {
+        let reports = [LOWER, UPPER, HOVER, OUT_OF_RANGE];
+        let all: Vec<_> = reports.windows(2).map(|pair| transition(pair[0], pair[1])).collect();
+        assert_eq!(
+            all,
+            [
+                vec![(LOWER, false), (UPPER, true)],
+                vec![(UPPER, false)],
+                vec![],
+            ]
+        );
+        // The lower button comes up exactly once, when the upper one goes down.
+        let lower_releases = all.concat().into_iter().filter(|&edge| edge == (LOWER, false)).count();
+        assert_eq!(lower_releases, 1);
+    }
+
+    #[test]
+    fn tracks_buttons_independently() {
+        // The tablet never reports both, but edges of a combined state stay per button.
+        let both = decode(LOWER) | decode(UPPER);
+        assert_eq!(edges(decode(LOWER), both), vec![(UPPER, true)]);
+        assert_eq!(edges(both, decode(UPPER)), vec![(LOWER, false)]);
+        assert_eq!(edges(both, 0), vec![(LOWER, false), (UPPER, false)]);
+        assert_eq!(edges(0, both), vec![(LOWER, true), (UPPER, true)]);
+    }
+
+    #[test]
+    fn leaving_range_releases_held_buttons() {
+        assert_eq!(transition(LOWER, OUT_OF_RANGE), vec![(LOWER, false)]);
+        assert_eq!(transition(UPPER, OUT_OF_RANGE), vec![(UPPER, false)]);
+        assert_eq!(transition(OUT_OF_RANGE, LOWER), vec![(LOWER, true)]);
+    }
+
+    #[test]
+    fn every_byte_sequence_keeps_presses_and_releases_paired() {
+        // Replays every pair of raw bytes, so no report can leave a button
+        // pressed that the next one doesn't account for.
+        for from in 0..=u8::MAX {
+            for to in 0..=u8::MAX {
+                let mut held = decode(from);
+                for (id, pressed) in transition(from, to) {
+                    let bit = 1 << PEN_BUTTON_IDS.iter().position(|&i| i == id).unwrap();
+                    assert_eq!(held & bit == 0, pressed, "{:#x} -> {:#x}", from, to);
+                    held ^= bit;
+                }
+                assert_eq!(held, decode(to), "{:#x} -> {:#x}", from, to);
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::time::{Duration, Instant};
+
+use evdev::Key;
+
+use super::actions::{is_button, ButtonSource};
+use super::DeviceDispatcher;
+use crate::config::RepeatMode;
+
+const MODIFIERS: [Key; 8] = [
+    Key::KEY_LEFTCTRL,
+    Key::KEY_RIGHTCTRL,
+    Key::KEY_LEFTSHIFT,
+    Key::KEY_RIGHTSHIFT,
+    Key::KEY_LEFTALT,
+    Key::KEY_RIGHTALT,
+    Key::KEY_LEFTMETA,
+    Key::KEY_RIGHTMETA,
+];
+
+/// Keys the driver repeats while their button is held. As on a keyboard, only
+/// the most recently pressed action repeats.
+pub struct HeldKeys {
+    source: ButtonSource,
+    keys: Vec<Key>,
+    next: Instant,
+    /// The action's own interval, or `None` to follow `KeyRepeat`.
+    interval: Option<Duration>,
+}
+
+impl HeldKeys {
+    /// Whether releasing `keys` from `source` ends this repeat.
+    fn released_by(&self, source: ButtonSource, keys: &[Key], repeat_modifiers: bool) -> bool {
+        self.source == source && self.keys == repeating_keys(keys, repeat_modifiers)
+    }
+}
+
+/// Keys of a chord that repeat: everything but mouse buttons, and modifiers
+/// unless `repeat_modifiers` is set.
+fn repeating_keys(keys: &[Key], repeat_modifiers: bool) -> Vec<Key> {
+    keys.iter()
+        .copied()
+        .filter(|&key| !is_button(key) && (repeat_modifiers || !MODIFIERS.contains(&key)))
+        .collect()
+}
+
+impl DeviceDispatcher {
+    /// Starts repeating `keys`, with the action's own delay and interval in
+    /// milliseconds if it has them, otherwise as `KeyRepeat` says.
+    pub(super) fn start_repeat(&mut self, source: ButtonSource, keys: &[Key], own: Option<(u64, u64)>) {
+        let repeat = self.config.read().unwrap().key_repeat;
+        let keys = repeating_keys(keys, repeat.repeat_modifiers);
+        let delay = match own {
+            Some((delay_ms, _)) => delay_ms,
+            None if repeat.mode == RepeatMode::Driver => repeat.delay_ms,
+            None => {
+                self.repeating = None;
+                return;
+            }
+        };
+        self.repeating = (!keys.is_empty()).then(|| HeldKeys {
+            source,
+            keys,
+            next: Instant::now() + Duration::from_millis(delay),
+            interval: own.map(|(_, interval_ms)| Duration::from_millis(interval_ms)),
+        });
+    }
+
+    pub(super) fn stop_repeat(&mut self, source: ButtonSource, keys: &[Key]) {
+        let repeat_modifiers = self.config.read().unwrap().key_repeat.repeat_modifiers;
+        if self
+            .repeating
+            .as_ref()
+            .is_some_and(|held| held.released_by(source, keys, repeat_modifiers))
+        {
+            self.repeating = None;
+        }
+    }
+
+    /// Taps the held keys again once their delay or interval has passed.
+    /// Modifiers that don't repeat stay down in between.
+    pub(super) fn repeat_keys(&mut self) {
+        let repeat = self.config.read().unwrap().key_repeat;
+        let Some(held) = &mut self.repeating else {
+            return;
+        };
+        let interval = match held.interval {
+            Some(interval) => interval,
+            None if repeat.mode == RepeatMode::Driver => Duration::from_millis(repeat.interval_ms),
+            None => {
+                self.repeating = None;
+                return;
+            }
+        };
+        let now = Instant::now();
+        if now < held.next {
+            return;
+        }
+        held.next = now + interval;
+
+        let (source, keys) = (held.source, held.keys.clone());
+        for state in [Self::RELEASED, Self::PRESSED] {
+            for &key in &keys {
+                self.emit_key(source, key, state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTRL_C: [Key; 2] = [Key::KEY_LEFTCTRL, Key::KEY_C];
+
+    fn held(source: ButtonSource, keys: &[Key]) -> HeldKeys {
+        HeldKeys {
+            source,
+            keys: repeating_keys(keys, false),
+            next: Instant::now(),
+            interval: None,
+        }
+    }
+
+    #[test]
+    fn modifiers_and_mouse_buttons_do_not_repeat() {
+        let keys = [Key::KEY_LEFTSHIFT, Key::KEY_RIGHTMETA, Key::BTN_LEFT, Key::KEY_A];
+        assert_eq!(repeating_keys(&keys, false), [Key::KEY_A]);
+        assert!(repeating_keys(&[Key::KEY_LEFTALT, Key::BTN_RIGHT], false).is_empty());
+    }
+
+    #[test]
+    fn modifiers_repeat_when_configured() {
+        let keys = [Key::KEY_LEFTSHIFT, Key::BTN_LEFT, Key::KEY_A];
+        assert_eq!(repeating_keys(&keys, true), [Key::KEY_LEFTSHIFT, Key::KEY_A]);
+    }
+
+    #[test]
+    fn only_the_same_source_and_chord_stop_a_repeat() {
+        let repeat = held(ButtonSource::Tablet, &CTRL_C);
+        assert!(repeat.released_by(ButtonSource::Tablet, &CTRL_C, false));
+        // The chord matches on the keys that repeat, so extra modifiers don't matter.
+        assert!(repeat.released_by(ButtonSource::Tablet, &[Key::KEY_C], false));
+        assert!(!repeat.released_by(ButtonSource::Pen, &CTRL_C, false));
+        assert!(!repeat.released_by(ButtonSource::Media, &CTRL_C, false));
+        assert!(!repeat.released_by(ButtonSource::Tablet, &[Key::KEY_LEFTCTRL, Key::KEY_V], false));
+    }
+
+    #[test]
+    fn repeating_modifiers_take_part_in_the_match() {
+        let repeat = HeldKeys {
+            keys: repeating_keys(&CTRL_C, true),
+            ..held(ButtonSource::Tablet, &CTRL_C)
+        };
+        assert!(repeat.released_by(ButtonSource::Tablet, &CTRL_C, true));
+        assert!(!repeat.released_by(ButtonSource::Tablet, &[Key::KEY_C], true));
+    }
+}